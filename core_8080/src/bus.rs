@@ -0,0 +1,243 @@
+use crate::core_error::CoreError;
+
+const ADDRESS_SPACE: usize = 0x10000;
+
+// Open-bus value returned for addresses that no region decodes
+const DEFAULT_OPEN_BUS: u8 = 0xFF;
+
+// Everything the CPU reads or writes goes through a Bus, so machines can decide what each
+// address is wired to
+pub trait Bus {
+    fn read_byte(&mut self, address: u16) -> Result<u8, CoreError>;
+
+    fn write_byte(&mut self, address: u16, data: u8) -> Result<(), CoreError>;
+
+    // Programs the backing store directly, ignoring write protection. Used to load ROM images.
+    fn load(&mut self, start_addr: u16, buffer: &[u8]) -> Result<(), CoreError>;
+}
+
+// Plain 64 KiB of RAM with no decoding, as used by CP/M programs and the tests
+pub struct FlatMemory {
+    ram: Box<[u8; ADDRESS_SPACE]>,
+}
+
+impl Default for FlatMemory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FlatMemory {
+    pub fn new() -> Self {
+        Self {
+            ram: Box::new([0; ADDRESS_SPACE]),
+        }
+    }
+}
+
+impl Bus for FlatMemory {
+    fn read_byte(&mut self, address: u16) -> Result<u8, CoreError> {
+        Ok(self.ram[address as usize])
+    }
+
+    fn write_byte(&mut self, address: u16, data: u8) -> Result<(), CoreError> {
+        self.ram[address as usize] = data;
+        Ok(())
+    }
+
+    fn load(&mut self, start_addr: u16, buffer: &[u8]) -> Result<(), CoreError> {
+        let start = start_addr as usize;
+        if start + buffer.len() > ADDRESS_SPACE {
+            return Err(CoreError::RomSizeError)
+        }
+        self.ram[start..start + buffer.len()].copy_from_slice(buffer);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    Rom,
+    Ram,
+    Unmapped { open_bus: u8 },
+    Mirror { base: u16, mask: u16 }, // Decodes as base | (address & mask)
+}
+
+struct MappedRegion {
+    start: u16,
+    end: u16,
+    region: Region,
+}
+
+// Address decoder built from a list of regions. Addresses are first masked to the lines the
+// board decodes, then the first region containing the address wins, and addresses outside every
+// region read as open bus.
+pub struct MemoryMap {
+    data: Box<[u8; ADDRESS_SPACE]>,
+    regions: Vec<MappedRegion>,
+    address_mask: u16,
+    strict_rom: bool,
+}
+
+impl Default for MemoryMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryMap {
+    pub fn new() -> Self {
+        Self {
+            data: Box::new([0; ADDRESS_SPACE]),
+            regions: Vec::new(),
+            address_mask: 0xFFFF,
+            strict_rom: false,
+        }
+    }
+
+    // The Midway 8080 board as MAME's mw8080bw driver maps it for Space Invaders: A15 isn't
+    // decoded, ROM sits at 0000-1FFF, work RAM at 2000-23FF and VRAM at 2400-3FFF, with the RAM
+    // mirrored at 6000-7FFF. Everything from 8000 up mirrors the lower half.
+    pub fn space_invaders() -> Self {
+        Self::midway(&[(0x0000, 0x1FFF)])
+    }
+
+    // The same board with ROM decoded only at the given inclusive ranges, such as the second
+    // bank at 4000-5FFF on later games. Anything else below the RAM mirror reads as open bus.
+    pub fn midway(rom: &[(u16, u16)]) -> Self {
+        let map = Self::new().address_mask(0x7FFF);
+        let map = rom.iter().fold(map, |map, &(start, end)| map.region(start, end, Region::Rom));
+        map.region(0x2000, 0x23FF, Region::Ram)
+            .region(0x2400, 0x3FFF, Region::Ram) // VRAM
+            .region(0x6000, 0x7FFF, Region::Mirror { base: 0x2000, mask: 0x1FFF })
+    }
+
+    pub fn region(mut self, start: u16, end: u16, region: Region) -> Self {
+        self.regions.push(MappedRegion { start, end, region });
+        self
+    }
+
+    // Address lines the board decodes. Addresses are masked before regions are looked up, so
+    // everything above the mask mirrors the space below it.
+    pub fn address_mask(mut self, mask: u16) -> Self {
+        self.address_mask = mask;
+        self
+    }
+
+    // Hardware silently ignores writes to ROM. In strict mode they are reported as
+    // RomWriteError instead, which catches game code scribbling over its own program.
+    pub fn strict_rom(mut self, strict: bool) -> Self {
+        self.strict_rom = strict;
+        self
+    }
+
+    fn find(&self, address: u16) -> Option<Region> {
+        self.regions.iter()
+            .find(|r| r.start <= address && address <= r.end)
+            .map(|r| r.region)
+    }
+
+    // Resolves mirrors to the address and region that actually respond. Only one level of
+    // mirroring is followed, and a mirror pointing at nothing reads as open bus.
+    fn decode(&self, address: u16) -> (u16, Region) {
+        let unmapped = Region::Unmapped { open_bus: DEFAULT_OPEN_BUS };
+        let address = address & self.address_mask;
+        match self.find(address) {
+            Some(Region::Mirror { base, mask }) => {
+                let target = base | (address & mask);
+                match self.find(target) {
+                    Some(Region::Mirror { .. }) | None => (target, unmapped),
+                    Some(region) => (target, region),
+                }
+            },
+            Some(region) => (address, region),
+            None => (address, unmapped),
+        }
+    }
+}
+
+impl Bus for MemoryMap {
+    fn read_byte(&mut self, address: u16) -> Result<u8, CoreError> {
+        match self.decode(address) {
+            (address, Region::Rom | Region::Ram) => Ok(self.data[address as usize]),
+            (_, Region::Unmapped { open_bus }) => Ok(open_bus),
+            (_, Region::Mirror { .. }) => unreachable!("decode resolves mirrors"),
+        }
+    }
+
+    fn write_byte(&mut self, address: u16, data: u8) -> Result<(), CoreError> {
+        match self.decode(address) {
+            (decoded, Region::Ram) => self.data[decoded as usize] = data,
+            (_, Region::Rom) if self.strict_rom => return Err(CoreError::RomWriteError { address }),
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn load(&mut self, start_addr: u16, buffer: &[u8]) -> Result<(), CoreError> {
+        let start = start_addr as usize;
+        if start + buffer.len() > ADDRESS_SPACE {
+            return Err(CoreError::RomSizeError)
+        }
+        self.data[start..start + buffer.len()].copy_from_slice(buffer);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine_config::MemoryLayout;
+
+    #[test]
+    fn space_invaders_rom_is_read_only() {
+        let mut map = MemoryMap::space_invaders();
+        map.load(0x0000, &[0xC3, 0x00]).unwrap();
+        map.write_byte(0x0000, 0x76).unwrap();
+        assert_eq!(map.read_byte(0x0000).unwrap(), 0xC3);
+
+        let mut map = MemoryMap::space_invaders().strict_rom(true);
+        assert!(matches!(map.write_byte(0x1FFF, 0x00), Err(CoreError::RomWriteError { address: 0x1FFF })));
+    }
+
+    #[test]
+    fn space_invaders_ram_mirror() {
+        let mut map = MemoryMap::space_invaders();
+        map.write_byte(0x2400, 0x55).unwrap();
+        assert_eq!(map.read_byte(0x6400).unwrap(), 0x55);
+        assert_eq!(map.read_byte(0xE400).unwrap(), 0x55);
+        map.write_byte(0x63FF, 0xAA).unwrap();
+        assert_eq!(map.read_byte(0x23FF).unwrap(), 0xAA);
+        map.write_byte(0xA000, 0x66).unwrap();
+        assert_eq!(map.read_byte(0x2000).unwrap(), 0x66);
+    }
+
+    #[test]
+    fn space_invaders_upper_mirror() {
+        let mut map = MemoryMap::space_invaders();
+        map.load(0x0000, &[0xC3]).unwrap();
+        assert_eq!(map.read_byte(0x8000).unwrap(), 0xC3);
+        map.load(0x4000, &[0x12]).unwrap();
+        assert_eq!(map.read_byte(0x4000).unwrap(), DEFAULT_OPEN_BUS);
+        assert_eq!(map.read_byte(0xC000).unwrap(), DEFAULT_OPEN_BUS);
+    }
+
+    #[test]
+    fn second_rom_bank() {
+        let mut map = MemoryLayout::extended().memory_map();
+        map.load(0x4000, &[0x12, 0x34]).unwrap();
+        map.write_byte(0x4000, 0x00).unwrap();
+        assert_eq!(map.read_byte(0x4000).unwrap(), 0x12);
+        assert_eq!(map.read_byte(0xC001).unwrap(), 0x34);
+    }
+
+    #[test]
+    fn unmapped_reads_open_bus() {
+        let mut map = MemoryMap::new()
+            .region(0x0000, 0x0FFF, Region::Ram)
+            .region(0x1000, 0x1FFF, Region::Unmapped { open_bus: 0x00 });
+        map.write_byte(0x1000, 0x12).unwrap();
+        assert_eq!(map.read_byte(0x1000).unwrap(), 0x00);
+        assert_eq!(map.read_byte(0x8000).unwrap(), DEFAULT_OPEN_BUS);
+    }
+}
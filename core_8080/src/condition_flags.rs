@@ -1,9 +1,16 @@
+pub const CARRY_BIT: u8 = 0x01;
+const ALWAYS_SET_BIT: u8 = 0x02;
+pub const PARITY_BIT: u8 = 0x04;
+pub const AUX_CARRY_BIT: u8 = 0x10;
+pub const ZERO_BIT: u8 = 0x40;
+pub const SIGN_BIT: u8 = 0x80;
+
 pub struct ConditionFlags {
     pub zero: bool,
     pub sign: bool,
     pub parity: bool,
     pub carry: bool,
-    // aux_carry: bool, // Not used by Space Invaders, so I'm ignoring it
+    pub aux_carry: bool,
 }
 
 impl ConditionFlags {
@@ -12,7 +19,27 @@ impl ConditionFlags {
             zero: false,
             sign: false,
             parity: false,
-            carry: false
+            carry: false,
+            aux_carry: false,
         }
     }
-}
\ No newline at end of file
+
+    // Flag byte as the 8080 pushes it with PUSH PSW: S Z 0 AC 0 P 1 CY
+    pub fn get_byte(&self) -> u8 {
+        let mut byte = ALWAYS_SET_BIT;
+        if self.carry { byte |= CARRY_BIT }
+        if self.parity { byte |= PARITY_BIT }
+        if self.aux_carry { byte |= AUX_CARRY_BIT }
+        if self.zero { byte |= ZERO_BIT }
+        if self.sign { byte |= SIGN_BIT }
+        byte
+    }
+
+    pub fn set_byte(&mut self, byte: u8) {
+        self.carry = byte & CARRY_BIT != 0;
+        self.parity = byte & PARITY_BIT != 0;
+        self.aux_carry = byte & AUX_CARRY_BIT != 0;
+        self.zero = byte & ZERO_BIT != 0;
+        self.sign = byte & SIGN_BIT != 0;
+    }
+}
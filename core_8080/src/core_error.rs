@@ -5,18 +5,30 @@ pub enum CoreError {
     #[error("invalid ROM size")]
     RomSizeError,
 
-    #[error("program counter out of bounds\n index: {index}")]
-    ProgramCounterError { index: u16 },
+    #[error("write to ROM\n address: {address:#06x}")]
+    RomWriteError { address: u16 },
 
-    #[error("program pointer overflow")]
-    ProgramCounterOverflow,
+    #[error("invalid DIP switch setting: {setting}")]
+    DipSwitchError { setting: String },
+
+    #[error("missing ROM file: {name}")]
+    MissingRomError { name: String },
+
+    #[error("bad ROM dump {name}: {reason}")]
+    RomChecksumError { name: String, reason: String },
+
+    #[error("could not read ROM {name}: {reason}")]
+    RomReadError { name: String, reason: String },
+
+    #[error("unrecognised ROM: {name}")]
+    UnknownRomError { name: String },
+
+    #[error("unknown game: {name}")]
+    UnknownGameError { name: String },
+
+    #[error("invalid overlay band: {band}")]
+    OverlayError { band: String },
 
-    #[error("attempted to read outside of RAM\n index: {index}")]
-    IndexError { index: u16 },
-    
     #[error("invalid opcode: {opcode}")]
     OpcodeError { opcode: u8 },
-
-    #[error("stack pointer overflow")]
-    StackPointerOverflow,
 }
\ No newline at end of file
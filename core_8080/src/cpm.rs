@@ -0,0 +1,127 @@
+// Minimal CP/M environment for running the standard 8080 diagnostic programs. The program is
+// loaded into the TPA at 0x0100, BDOS calls through 0x0005 are trapped and serviced here, and a
+// jump to the warm boot vector at 0x0000 ends the run.
+use crate::CPU;
+use crate::core_error::CoreError;
+
+const WARM_BOOT_ADDR: u16 = 0x0000;
+const BDOS_ADDR: u16 = 0x0005;
+const TPA_ADDR: u16 = 0x0100;
+const TPA_TOP: u16 = 0xF000;
+
+const C_WRITE: u8 = 0x02;
+const C_WRITESTR: u8 = 0x09;
+
+pub struct CpmRunner {
+    pub cpu: CPU,
+    pub output: String,
+    pub instructions: u64,
+    pub cycles: u64,
+}
+
+impl CpmRunner {
+    pub fn new(program: &[u8]) -> Result<Self, CoreError> {
+        let mut cpu = CPU::new();
+        // JMP TPA_TOP at the BDOS entry, so programs that size memory from 0x0006 see the TPA top
+        cpu.memory.write_byte(BDOS_ADDR, 0xC3)?;
+        cpu.memory.write_two_bytes(BDOS_ADDR + 1, TPA_TOP)?;
+        cpu.memory.stack_pointer = TPA_TOP;
+        cpu.load_rom_at(program, TPA_ADDR)?;
+        Ok(Self {
+            cpu,
+            output: String::new(),
+            instructions: 0,
+            cycles: 0,
+        })
+    }
+
+    // Runs until the program warm boots, returning false if it was still running after max_instructions
+    pub fn run(&mut self, max_instructions: u64) -> Result<bool, CoreError> {
+        while self.instructions < max_instructions {
+            match self.cpu.memory.program_counter {
+                WARM_BOOT_ADDR => return Ok(true),
+                BDOS_ADDR => {
+                    self.bdos_call()?;
+                    self.cpu.memory.program_counter = self.cpu.memory.pop_stack()?;
+                },
+                _ => {
+                    self.cycles += self.cpu.tick()? as u64;
+                    self.instructions += 1;
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    fn bdos_call(&mut self) -> Result<(), CoreError> {
+        match self.cpu.registers.bc_reg.low {
+            C_WRITE => self.output.push(self.cpu.registers.de_reg.low as char),
+            C_WRITESTR => {
+                let mut addr = self.cpu.registers.de_reg.get_pair();
+                loop {
+                    let byte = self.cpu.memory.read_byte(addr)?;
+                    if byte == b'$' {
+                        break;
+                    }
+                    self.output.push(byte as char);
+                    addr = addr.wrapping_add(1);
+                }
+            },
+            _ => (),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn load_fixture(name: &str) -> Vec<u8> {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name);
+        fs::read(&path).unwrap_or_else(|e| panic!("could not read {}: {}", path.display(), e))
+    }
+
+    fn run_fixture(name: &str, max_instructions: u64) -> String {
+        let mut runner = CpmRunner::new(&load_fixture(name)).unwrap();
+        let finished = runner.run(max_instructions).unwrap();
+        assert!(finished, "{} did not finish after {} instructions:\n{}", name, max_instructions, runner.output);
+        runner.output
+    }
+
+    #[test]
+    fn bdos_console_output() {
+        let output = run_fixture("BDOSTEST.COM", 100);
+        assert_eq!(output, "HELLO FROM CP/M!");
+    }
+
+    #[test]
+    #[ignore = "requires TST8080.COM in tests/fixtures"]
+    fn tst8080() {
+        let output = run_fixture("TST8080.COM", 10_000_000);
+        assert!(output.contains("CPU IS OPERATIONAL"), "{}", output);
+    }
+
+    #[test]
+    #[ignore = "requires 8080PRE.COM in tests/fixtures"]
+    fn prelim_8080() {
+        let output = run_fixture("8080PRE.COM", 10_000_000);
+        assert!(output.contains("Preliminary tests complete"), "{}", output);
+    }
+
+    #[test]
+    #[ignore = "requires CPUTEST.COM in tests/fixtures"]
+    fn cputest() {
+        let output = run_fixture("CPUTEST.COM", 1_000_000_000);
+        assert!(output.contains("CPU TESTS OK"), "{}", output);
+    }
+
+    #[test]
+    #[ignore = "requires 8080EXM.COM in tests/fixtures, takes several minutes in release builds"]
+    fn exerciser_8080() {
+        let output = run_fixture("8080EXM.COM", 10_000_000_000);
+        assert!(!output.contains("ERROR"), "{}", output);
+    }
+}
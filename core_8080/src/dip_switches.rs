@@ -0,0 +1,85 @@
+use std::str::FromStr;
+use crate::core_error::CoreError;
+
+// Port 2 bits driven by the operator DIP switches
+const SHIPS_MASK: u8 = 0x03;
+const BONUS_LIFE_BIT: u8 = 0x08;
+const COIN_INFO_BIT: u8 = 0x80;
+pub const DIP_SWITCH_MASK: u8 = SHIPS_MASK | BONUS_LIFE_BIT | COIN_INFO_BIT;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ships {
+    Three,
+    Four,
+    Five,
+    Six,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BonusLife {
+    At1000,
+    At1500,
+}
+
+// Operator settings read by the game from port 2. The default matches a stock cabinet:
+// three ships, a bonus ship at 1000 points and coin info shown in the attract mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DipSwitches {
+    pub ships: Ships,
+    pub bonus_life: BonusLife,
+    pub coin_info: bool,
+}
+
+impl Default for DipSwitches {
+    fn default() -> Self {
+        Self {
+            ships: Ships::Three,
+            bonus_life: BonusLife::At1000,
+            coin_info: true,
+        }
+    }
+}
+
+impl DipSwitches {
+    // Port 2 bits for these settings, only within DIP_SWITCH_MASK
+    pub fn port2_bits(&self) -> u8 {
+        let ships = match self.ships {
+            Ships::Three => 0x00,
+            Ships::Four => 0x01,
+            Ships::Five => 0x02,
+            Ships::Six => 0x03,
+        };
+        let bonus_life = match self.bonus_life {
+            BonusLife::At1000 => BONUS_LIFE_BIT,
+            BonusLife::At1500 => 0x00,
+        };
+        let coin_info = if self.coin_info { 0x00 } else { COIN_INFO_BIT }; // Active low
+        ships | bonus_life | coin_info
+    }
+}
+
+// Parses comma separated settings such as "ships=5,bonus=1500,coin-info=off". Settings that
+// are left out keep their default.
+impl FromStr for DipSwitches {
+    type Err = CoreError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut dips = DipSwitches::default();
+        for setting in s.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let invalid = || CoreError::DipSwitchError { setting: setting.to_string() };
+            let (name, value) = setting.split_once('=').ok_or_else(invalid)?;
+            match (name.trim(), value.trim()) {
+                ("ships", "3") => dips.ships = Ships::Three,
+                ("ships", "4") => dips.ships = Ships::Four,
+                ("ships", "5") => dips.ships = Ships::Five,
+                ("ships", "6") => dips.ships = Ships::Six,
+                ("bonus", "1000") => dips.bonus_life = BonusLife::At1000,
+                ("bonus", "1500") => dips.bonus_life = BonusLife::At1500,
+                ("coin-info", "on") => dips.coin_info = true,
+                ("coin-info", "off") => dips.coin_info = false,
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(dips)
+    }
+}
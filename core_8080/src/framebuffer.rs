@@ -0,0 +1,105 @@
+// The monitor is mounted on its side, so the 256x224 raster the hardware scans out is seen as
+// a 224 wide, 256 tall picture
+pub const SCREEN_WIDTH: usize = 224;
+pub const SCREEN_HEIGHT: usize = 256;
+
+pub const VRAM_START: u16 = 0x2400;
+pub const VRAM_SIZE: usize = SCREEN_WIDTH * SCREEN_HEIGHT / 8;
+
+const LIT_RGBA: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+const UNLIT_RGBA: [u8; 4] = [0x00, 0x00, 0x00, 0xFF];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    Rgba,      // 4 bytes a pixel
+    Luminance, // 1 byte a pixel, 0x00 or 0xFF
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(&self) -> usize {
+        match self {
+            PixelFormat::Rgba => 4,
+            PixelFormat::Luminance => 1,
+        }
+    }
+}
+
+// The screen as the player sees it, one bool a pixel in rows from the top left
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Framebuffer {
+    pixels: Vec<bool>,
+}
+
+impl Framebuffer {
+    // Each VRAM byte holds 8 pixels of a raster line, least significant bit first. Raster lines
+    // run bottom to top on the rotated monitor, and successive lines go left to right.
+    pub fn decode(vram: &[u8]) -> Self {
+        let mut pixels = vec![false; SCREEN_WIDTH * SCREEN_HEIGHT];
+        for (offset, &byte) in vram.iter().take(VRAM_SIZE).enumerate() {
+            let x = offset / (SCREEN_HEIGHT / 8);
+            let raster_y = (offset % (SCREEN_HEIGHT / 8)) * 8;
+            for bit in 0..8 {
+                if byte & (1 << bit) != 0 {
+                    let y = SCREEN_HEIGHT - 1 - (raster_y + bit);
+                    pixels[y * SCREEN_WIDTH + x] = true;
+                }
+            }
+        }
+        Self { pixels }
+    }
+
+    pub fn is_lit(&self, x: usize, y: usize) -> bool {
+        self.pixels[y * SCREEN_WIDTH + x]
+    }
+
+    // Cocktail cabinets turn the picture upside down for player 2
+    pub fn flipped(&self) -> Self {
+        Self {
+            pixels: self.pixels.iter().rev().copied().collect(),
+        }
+    }
+
+    pub fn render(&self, format: PixelFormat) -> Vec<u8> {
+        match format {
+            PixelFormat::Rgba => self.pixels.iter()
+                .flat_map(|&lit| if lit { LIT_RGBA } else { UNLIT_RGBA })
+                .collect(),
+            PixelFormat::Luminance => self.pixels.iter()
+                .map(|&lit| if lit { 0xFF } else { 0x00 })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vram_is_rotated() {
+        let mut vram = vec![0x00; VRAM_SIZE];
+        vram[0] = 0x01; // First raster pixel, bottom left
+        vram[31] = 0x80; // End of the first raster line, top left
+        vram[VRAM_SIZE - 1] = 0x80; // Last raster pixel, top right
+        let screen = Framebuffer::decode(&vram);
+        assert!(screen.is_lit(0, SCREEN_HEIGHT - 1));
+        assert!(screen.is_lit(0, 0));
+        assert!(screen.is_lit(SCREEN_WIDTH - 1, 0));
+        assert!(!screen.is_lit(1, 0));
+        assert_eq!(screen.pixels.iter().filter(|&&lit| lit).count(), 3);
+        assert!(screen.flipped().is_lit(SCREEN_WIDTH - 1, SCREEN_HEIGHT - 1));
+    }
+
+    #[test]
+    fn pixel_formats() {
+        let mut vram = vec![0x00; VRAM_SIZE];
+        vram[31] = 0x80;
+        let screen = Framebuffer::decode(&vram);
+        let rgba = screen.render(PixelFormat::Rgba);
+        assert_eq!(rgba.len(), SCREEN_WIDTH * SCREEN_HEIGHT * PixelFormat::Rgba.bytes_per_pixel());
+        assert_eq!(rgba[0..8], [0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0xFF]);
+        let luminance = screen.render(PixelFormat::Luminance);
+        assert_eq!(luminance.len(), SCREEN_WIDTH * SCREEN_HEIGHT);
+        assert_eq!(luminance[0..2], [0xFF, 0x00]);
+    }
+}
@@ -1,36 +1,311 @@
-use crate::{register_pair::RegisterPair, CPU, ConditionFlagsStatus};
+use crate::condition_flags::{CARRY_BIT, PARITY_BIT, AUX_CARRY_BIT, ZERO_BIT, SIGN_BIT};
 
-pub fn stax(register: &mut RegisterPair, accumulator: &mut u8, memory: &mut [u8]) {
-    memory[register.get_pair() as usize] = *accumulator;
+const NONE: u8 = 0;
+const CY: u8 = CARRY_BIT;
+const SZAP: u8 = SIGN_BIT | ZERO_BIT | AUX_CARRY_BIT | PARITY_BIT;
+const ALL: u8 = SZAP | CARRY_BIT;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operand {
+    None,
+    D8,  // Immediate byte, also used for IN/OUT port numbers
+    D16, // Immediate word
+    A16, // Address word
 }
 
-pub fn inx(register: &mut RegisterPair) {
-    register.set_pair(register.get_pair().wrapping_add(1));
+#[derive(Debug)]
+pub struct Instruction {
+    pub mnemonic: &'static str,
+    pub operand: Operand,
+    pub length: u8,
+    pub cycles: u32, // Not-taken count for conditional calls and returns
+    pub flags: u8, // Flags written, as a mask of the PSW flag bits
+    pub documented: bool,
 }
 
-pub fn inr(register: &mut u8) -> ConditionFlagsStatus {
-    *register = register.wrapping_add(1);
-    let mut flags = ConditionFlagsStatus::new();
-    flags.zero = Some(*register == 0);
-    flags.sign = Some(*register & 0x80 != 0);
-    flags.parity = Some(parity(*register));
-    flags
+impl Instruction {
+    // Mnemonic with its operand placeholder replaced by the given value
+    pub fn format(&self, operand: u16) -> String {
+        match self.operand {
+            Operand::None => self.mnemonic.to_string(),
+            Operand::D8 => self.mnemonic.replace("d8", &format!("{:#04x}", operand as u8)),
+            Operand::D16 => self.mnemonic.replace("d16", &format!("{:#06x}", operand)),
+            Operand::A16 => self.mnemonic.replace("a16", &format!("{:#06x}", operand)),
+        }
+    }
 }
 
-fn parity(a: u8) -> bool {
-    // Shamelessly inspired by https://graphics.stanford.edu/~seander/bithacks.html#ParityParallel
-    let mut a = a;
-    a ^= a >> 4;
-    a &= 0x0F;
-    return (0x6996 >> a) & 0x1 == 1;
+const fn op(mnemonic: &'static str, operand: Operand, cycles: u32, flags: u8) -> Instruction {
+    let length = match operand {
+        Operand::None => 1,
+        Operand::D8 => 2,
+        Operand::D16 | Operand::A16 => 3,
+    };
+    Instruction { mnemonic, operand, length, cycles, flags, documented: true }
 }
 
-mod tests {
-    use super::*;
-    #[test]
-    fn op_inx() {
-        let mut reg = RegisterPair {high: 0xFF, low: 0xFF};
-        inx(&mut reg);
-        assert_eq!(reg.get_pair(), 0);
-    }
+// Undocumented opcode that real silicon executes as the named instruction
+const fn alias(mnemonic: &'static str, operand: Operand, cycles: u32, flags: u8) -> Instruction {
+    let instruction = op(mnemonic, operand, cycles, flags);
+    Instruction { documented: false, ..instruction }
 }
+
+// Every opcode with its datasheet length and T-states, indexed by opcode
+pub static INSTRUCTIONS: [Instruction; 256] = [
+    op(   "NOP",        Operand::None,  4, NONE),  // 0x00
+    op(   "LXI B,d16",  Operand::D16,  10, NONE),  // 0x01
+    op(   "STAX B",     Operand::None,  7, NONE),  // 0x02
+    op(   "INX B",      Operand::None,  5, NONE),  // 0x03
+    op(   "INR B",      Operand::None,  5, SZAP),  // 0x04
+    op(   "DCR B",      Operand::None,  5, SZAP),  // 0x05
+    op(   "MVI B,d8",   Operand::D8,    7, NONE),  // 0x06
+    op(   "RLC",        Operand::None,  4, CY),    // 0x07
+    alias("NOP",        Operand::None,  4, NONE),  // 0x08
+    op(   "DAD B",      Operand::None, 10, CY),    // 0x09
+    op(   "LDAX B",     Operand::None,  7, NONE),  // 0x0A
+    op(   "DCX B",      Operand::None,  5, NONE),  // 0x0B
+    op(   "INR C",      Operand::None,  5, SZAP),  // 0x0C
+    op(   "DCR C",      Operand::None,  5, SZAP),  // 0x0D
+    op(   "MVI C,d8",   Operand::D8,    7, NONE),  // 0x0E
+    op(   "RRC",        Operand::None,  4, CY),    // 0x0F
+    alias("NOP",        Operand::None,  4, NONE),  // 0x10
+    op(   "LXI D,d16",  Operand::D16,  10, NONE),  // 0x11
+    op(   "STAX D",     Operand::None,  7, NONE),  // 0x12
+    op(   "INX D",      Operand::None,  5, NONE),  // 0x13
+    op(   "INR D",      Operand::None,  5, SZAP),  // 0x14
+    op(   "DCR D",      Operand::None,  5, SZAP),  // 0x15
+    op(   "MVI D,d8",   Operand::D8,    7, NONE),  // 0x16
+    op(   "RAL",        Operand::None,  4, CY),    // 0x17
+    alias("NOP",        Operand::None,  4, NONE),  // 0x18
+    op(   "DAD D",      Operand::None, 10, CY),    // 0x19
+    op(   "LDAX D",     Operand::None,  7, NONE),  // 0x1A
+    op(   "DCX D",      Operand::None,  5, NONE),  // 0x1B
+    op(   "INR E",      Operand::None,  5, SZAP),  // 0x1C
+    op(   "DCR E",      Operand::None,  5, SZAP),  // 0x1D
+    op(   "MVI E,d8",   Operand::D8,    7, NONE),  // 0x1E
+    op(   "RAR",        Operand::None,  4, CY),    // 0x1F
+    alias("NOP",        Operand::None,  4, NONE),  // 0x20
+    op(   "LXI H,d16",  Operand::D16,  10, NONE),  // 0x21
+    op(   "SHLD a16",   Operand::A16,  16, NONE),  // 0x22
+    op(   "INX H",      Operand::None,  5, NONE),  // 0x23
+    op(   "INR H",      Operand::None,  5, SZAP),  // 0x24
+    op(   "DCR H",      Operand::None,  5, SZAP),  // 0x25
+    op(   "MVI H,d8",   Operand::D8,    7, NONE),  // 0x26
+    op(   "DAA",        Operand::None,  4, ALL),   // 0x27
+    alias("NOP",        Operand::None,  4, NONE),  // 0x28
+    op(   "DAD H",      Operand::None, 10, CY),    // 0x29
+    op(   "LHLD a16",   Operand::A16,  16, NONE),  // 0x2A
+    op(   "DCX H",      Operand::None,  5, NONE),  // 0x2B
+    op(   "INR L",      Operand::None,  5, SZAP),  // 0x2C
+    op(   "DCR L",      Operand::None,  5, SZAP),  // 0x2D
+    op(   "MVI L,d8",   Operand::D8,    7, NONE),  // 0x2E
+    op(   "CMA",        Operand::None,  4, NONE),  // 0x2F
+    alias("NOP",        Operand::None,  4, NONE),  // 0x30
+    op(   "LXI SP,d16", Operand::D16,  10, NONE),  // 0x31
+    op(   "STA a16",    Operand::A16,  13, NONE),  // 0x32
+    op(   "INX SP",     Operand::None,  5, NONE),  // 0x33
+    op(   "INR M",      Operand::None, 10, SZAP),  // 0x34
+    op(   "DCR M",      Operand::None, 10, SZAP),  // 0x35
+    op(   "MVI M,d8",   Operand::D8,   10, NONE),  // 0x36
+    op(   "STC",        Operand::None,  4, CY),    // 0x37
+    alias("NOP",        Operand::None,  4, NONE),  // 0x38
+    op(   "DAD SP",     Operand::None, 10, CY),    // 0x39
+    op(   "LDA a16",    Operand::A16,  13, NONE),  // 0x3A
+    op(   "DCX SP",     Operand::None,  5, NONE),  // 0x3B
+    op(   "INR A",      Operand::None,  5, SZAP),  // 0x3C
+    op(   "DCR A",      Operand::None,  5, SZAP),  // 0x3D
+    op(   "MVI A,d8",   Operand::D8,    7, NONE),  // 0x3E
+    op(   "CMC",        Operand::None,  4, CY),    // 0x3F
+    op(   "MOV B,B",    Operand::None,  5, NONE),  // 0x40
+    op(   "MOV B,C",    Operand::None,  5, NONE),  // 0x41
+    op(   "MOV B,D",    Operand::None,  5, NONE),  // 0x42
+    op(   "MOV B,E",    Operand::None,  5, NONE),  // 0x43
+    op(   "MOV B,H",    Operand::None,  5, NONE),  // 0x44
+    op(   "MOV B,L",    Operand::None,  5, NONE),  // 0x45
+    op(   "MOV B,M",    Operand::None,  7, NONE),  // 0x46
+    op(   "MOV B,A",    Operand::None,  5, NONE),  // 0x47
+    op(   "MOV C,B",    Operand::None,  5, NONE),  // 0x48
+    op(   "MOV C,C",    Operand::None,  5, NONE),  // 0x49
+    op(   "MOV C,D",    Operand::None,  5, NONE),  // 0x4A
+    op(   "MOV C,E",    Operand::None,  5, NONE),  // 0x4B
+    op(   "MOV C,H",    Operand::None,  5, NONE),  // 0x4C
+    op(   "MOV C,L",    Operand::None,  5, NONE),  // 0x4D
+    op(   "MOV C,M",    Operand::None,  7, NONE),  // 0x4E
+    op(   "MOV C,A",    Operand::None,  5, NONE),  // 0x4F
+    op(   "MOV D,B",    Operand::None,  5, NONE),  // 0x50
+    op(   "MOV D,C",    Operand::None,  5, NONE),  // 0x51
+    op(   "MOV D,D",    Operand::None,  5, NONE),  // 0x52
+    op(   "MOV D,E",    Operand::None,  5, NONE),  // 0x53
+    op(   "MOV D,H",    Operand::None,  5, NONE),  // 0x54
+    op(   "MOV D,L",    Operand::None,  5, NONE),  // 0x55
+    op(   "MOV D,M",    Operand::None,  7, NONE),  // 0x56
+    op(   "MOV D,A",    Operand::None,  5, NONE),  // 0x57
+    op(   "MOV E,B",    Operand::None,  5, NONE),  // 0x58
+    op(   "MOV E,C",    Operand::None,  5, NONE),  // 0x59
+    op(   "MOV E,D",    Operand::None,  5, NONE),  // 0x5A
+    op(   "MOV E,E",    Operand::None,  5, NONE),  // 0x5B
+    op(   "MOV E,H",    Operand::None,  5, NONE),  // 0x5C
+    op(   "MOV E,L",    Operand::None,  5, NONE),  // 0x5D
+    op(   "MOV E,M",    Operand::None,  7, NONE),  // 0x5E
+    op(   "MOV E,A",    Operand::None,  5, NONE),  // 0x5F
+    op(   "MOV H,B",    Operand::None,  5, NONE),  // 0x60
+    op(   "MOV H,C",    Operand::None,  5, NONE),  // 0x61
+    op(   "MOV H,D",    Operand::None,  5, NONE),  // 0x62
+    op(   "MOV H,E",    Operand::None,  5, NONE),  // 0x63
+    op(   "MOV H,H",    Operand::None,  5, NONE),  // 0x64
+    op(   "MOV H,L",    Operand::None,  5, NONE),  // 0x65
+    op(   "MOV H,M",    Operand::None,  7, NONE),  // 0x66
+    op(   "MOV H,A",    Operand::None,  5, NONE),  // 0x67
+    op(   "MOV L,B",    Operand::None,  5, NONE),  // 0x68
+    op(   "MOV L,C",    Operand::None,  5, NONE),  // 0x69
+    op(   "MOV L,D",    Operand::None,  5, NONE),  // 0x6A
+    op(   "MOV L,E",    Operand::None,  5, NONE),  // 0x6B
+    op(   "MOV L,H",    Operand::None,  5, NONE),  // 0x6C
+    op(   "MOV L,L",    Operand::None,  5, NONE),  // 0x6D
+    op(   "MOV L,M",    Operand::None,  7, NONE),  // 0x6E
+    op(   "MOV L,A",    Operand::None,  5, NONE),  // 0x6F
+    op(   "MOV M,B",    Operand::None,  7, NONE),  // 0x70
+    op(   "MOV M,C",    Operand::None,  7, NONE),  // 0x71
+    op(   "MOV M,D",    Operand::None,  7, NONE),  // 0x72
+    op(   "MOV M,E",    Operand::None,  7, NONE),  // 0x73
+    op(   "MOV M,H",    Operand::None,  7, NONE),  // 0x74
+    op(   "MOV M,L",    Operand::None,  7, NONE),  // 0x75
+    op(   "HLT",        Operand::None,  7, NONE),  // 0x76
+    op(   "MOV M,A",    Operand::None,  7, NONE),  // 0x77
+    op(   "MOV A,B",    Operand::None,  5, NONE),  // 0x78
+    op(   "MOV A,C",    Operand::None,  5, NONE),  // 0x79
+    op(   "MOV A,D",    Operand::None,  5, NONE),  // 0x7A
+    op(   "MOV A,E",    Operand::None,  5, NONE),  // 0x7B
+    op(   "MOV A,H",    Operand::None,  5, NONE),  // 0x7C
+    op(   "MOV A,L",    Operand::None,  5, NONE),  // 0x7D
+    op(   "MOV A,M",    Operand::None,  7, NONE),  // 0x7E
+    op(   "MOV A,A",    Operand::None,  5, NONE),  // 0x7F
+    op(   "ADD B",      Operand::None,  4, ALL),   // 0x80
+    op(   "ADD C",      Operand::None,  4, ALL),   // 0x81
+    op(   "ADD D",      Operand::None,  4, ALL),   // 0x82
+    op(   "ADD E",      Operand::None,  4, ALL),   // 0x83
+    op(   "ADD H",      Operand::None,  4, ALL),   // 0x84
+    op(   "ADD L",      Operand::None,  4, ALL),   // 0x85
+    op(   "ADD M",      Operand::None,  7, ALL),   // 0x86
+    op(   "ADD A",      Operand::None,  4, ALL),   // 0x87
+    op(   "ADC B",      Operand::None,  4, ALL),   // 0x88
+    op(   "ADC C",      Operand::None,  4, ALL),   // 0x89
+    op(   "ADC D",      Operand::None,  4, ALL),   // 0x8A
+    op(   "ADC E",      Operand::None,  4, ALL),   // 0x8B
+    op(   "ADC H",      Operand::None,  4, ALL),   // 0x8C
+    op(   "ADC L",      Operand::None,  4, ALL),   // 0x8D
+    op(   "ADC M",      Operand::None,  7, ALL),   // 0x8E
+    op(   "ADC A",      Operand::None,  4, ALL),   // 0x8F
+    op(   "SUB B",      Operand::None,  4, ALL),   // 0x90
+    op(   "SUB C",      Operand::None,  4, ALL),   // 0x91
+    op(   "SUB D",      Operand::None,  4, ALL),   // 0x92
+    op(   "SUB E",      Operand::None,  4, ALL),   // 0x93
+    op(   "SUB H",      Operand::None,  4, ALL),   // 0x94
+    op(   "SUB L",      Operand::None,  4, ALL),   // 0x95
+    op(   "SUB M",      Operand::None,  7, ALL),   // 0x96
+    op(   "SUB A",      Operand::None,  4, ALL),   // 0x97
+    op(   "SBB B",      Operand::None,  4, ALL),   // 0x98
+    op(   "SBB C",      Operand::None,  4, ALL),   // 0x99
+    op(   "SBB D",      Operand::None,  4, ALL),   // 0x9A
+    op(   "SBB E",      Operand::None,  4, ALL),   // 0x9B
+    op(   "SBB H",      Operand::None,  4, ALL),   // 0x9C
+    op(   "SBB L",      Operand::None,  4, ALL),   // 0x9D
+    op(   "SBB M",      Operand::None,  7, ALL),   // 0x9E
+    op(   "SBB A",      Operand::None,  4, ALL),   // 0x9F
+    op(   "ANA B",      Operand::None,  4, ALL),   // 0xA0
+    op(   "ANA C",      Operand::None,  4, ALL),   // 0xA1
+    op(   "ANA D",      Operand::None,  4, ALL),   // 0xA2
+    op(   "ANA E",      Operand::None,  4, ALL),   // 0xA3
+    op(   "ANA H",      Operand::None,  4, ALL),   // 0xA4
+    op(   "ANA L",      Operand::None,  4, ALL),   // 0xA5
+    op(   "ANA M",      Operand::None,  7, ALL),   // 0xA6
+    op(   "ANA A",      Operand::None,  4, ALL),   // 0xA7
+    op(   "XRA B",      Operand::None,  4, ALL),   // 0xA8
+    op(   "XRA C",      Operand::None,  4, ALL),   // 0xA9
+    op(   "XRA D",      Operand::None,  4, ALL),   // 0xAA
+    op(   "XRA E",      Operand::None,  4, ALL),   // 0xAB
+    op(   "XRA H",      Operand::None,  4, ALL),   // 0xAC
+    op(   "XRA L",      Operand::None,  4, ALL),   // 0xAD
+    op(   "XRA M",      Operand::None,  7, ALL),   // 0xAE
+    op(   "XRA A",      Operand::None,  4, ALL),   // 0xAF
+    op(   "ORA B",      Operand::None,  4, ALL),   // 0xB0
+    op(   "ORA C",      Operand::None,  4, ALL),   // 0xB1
+    op(   "ORA D",      Operand::None,  4, ALL),   // 0xB2
+    op(   "ORA E",      Operand::None,  4, ALL),   // 0xB3
+    op(   "ORA H",      Operand::None,  4, ALL),   // 0xB4
+    op(   "ORA L",      Operand::None,  4, ALL),   // 0xB5
+    op(   "ORA M",      Operand::None,  7, ALL),   // 0xB6
+    op(   "ORA A",      Operand::None,  4, ALL),   // 0xB7
+    op(   "CMP B",      Operand::None,  4, ALL),   // 0xB8
+    op(   "CMP C",      Operand::None,  4, ALL),   // 0xB9
+    op(   "CMP D",      Operand::None,  4, ALL),   // 0xBA
+    op(   "CMP E",      Operand::None,  4, ALL),   // 0xBB
+    op(   "CMP H",      Operand::None,  4, ALL),   // 0xBC
+    op(   "CMP L",      Operand::None,  4, ALL),   // 0xBD
+    op(   "CMP M",      Operand::None,  7, ALL),   // 0xBE
+    op(   "CMP A",      Operand::None,  4, ALL),   // 0xBF
+    op(   "RNZ",        Operand::None,  5, NONE),  // 0xC0
+    op(   "POP B",      Operand::None, 10, NONE),  // 0xC1
+    op(   "JNZ a16",    Operand::A16,  10, NONE),  // 0xC2
+    op(   "JMP a16",    Operand::A16,  10, NONE),  // 0xC3
+    op(   "CNZ a16",    Operand::A16,  11, NONE),  // 0xC4
+    op(   "PUSH B",     Operand::None, 11, NONE),  // 0xC5
+    op(   "ADI d8",     Operand::D8,    7, ALL),   // 0xC6
+    op(   "RST 0",      Operand::None, 11, NONE),  // 0xC7
+    op(   "RZ",         Operand::None,  5, NONE),  // 0xC8
+    op(   "RET",        Operand::None, 10, NONE),  // 0xC9
+    op(   "JZ a16",     Operand::A16,  10, NONE),  // 0xCA
+    alias("JMP a16",    Operand::A16,  10, NONE),  // 0xCB
+    op(   "CZ a16",     Operand::A16,  11, NONE),  // 0xCC
+    op(   "CALL a16",   Operand::A16,  17, NONE),  // 0xCD
+    op(   "ACI d8",     Operand::D8,    7, ALL),   // 0xCE
+    op(   "RST 1",      Operand::None, 11, NONE),  // 0xCF
+    op(   "RNC",        Operand::None,  5, NONE),  // 0xD0
+    op(   "POP D",      Operand::None, 10, NONE),  // 0xD1
+    op(   "JNC a16",    Operand::A16,  10, NONE),  // 0xD2
+    op(   "OUT d8",     Operand::D8,   10, NONE),  // 0xD3
+    op(   "CNC a16",    Operand::A16,  11, NONE),  // 0xD4
+    op(   "PUSH D",     Operand::None, 11, NONE),  // 0xD5
+    op(   "SUI d8",     Operand::D8,    7, ALL),   // 0xD6
+    op(   "RST 2",      Operand::None, 11, NONE),  // 0xD7
+    op(   "RC",         Operand::None,  5, NONE),  // 0xD8
+    alias("RET",        Operand::None, 10, NONE),  // 0xD9
+    op(   "JC a16",     Operand::A16,  10, NONE),  // 0xDA
+    op(   "IN d8",      Operand::D8,   10, NONE),  // 0xDB
+    op(   "CC a16",     Operand::A16,  11, NONE),  // 0xDC
+    alias("CALL a16",   Operand::A16,  17, NONE),  // 0xDD
+    op(   "SBI d8",     Operand::D8,    7, ALL),   // 0xDE
+    op(   "RST 3",      Operand::None, 11, NONE),  // 0xDF
+    op(   "RPO",        Operand::None,  5, NONE),  // 0xE0
+    op(   "POP H",      Operand::None, 10, NONE),  // 0xE1
+    op(   "JPO a16",    Operand::A16,  10, NONE),  // 0xE2
+    op(   "XTHL",       Operand::None, 18, NONE),  // 0xE3
+    op(   "CPO a16",    Operand::A16,  11, NONE),  // 0xE4
+    op(   "PUSH H",     Operand::None, 11, NONE),  // 0xE5
+    op(   "ANI d8",     Operand::D8,    7, ALL),   // 0xE6
+    op(   "RST 4",      Operand::None, 11, NONE),  // 0xE7
+    op(   "RPE",        Operand::None,  5, NONE),  // 0xE8
+    op(   "PCHL",       Operand::None,  5, NONE),  // 0xE9
+    op(   "JPE a16",    Operand::A16,  10, NONE),  // 0xEA
+    op(   "XCHG",       Operand::None,  4, NONE),  // 0xEB
+    op(   "CPE a16",    Operand::A16,  11, NONE),  // 0xEC
+    alias("CALL a16",   Operand::A16,  17, NONE),  // 0xED
+    op(   "XRI d8",     Operand::D8,    7, ALL),   // 0xEE
+    op(   "RST 5",      Operand::None, 11, NONE),  // 0xEF
+    op(   "RP",         Operand::None,  5, NONE),  // 0xF0
+    op(   "POP PSW",    Operand::None, 10, ALL),   // 0xF1
+    op(   "JP a16",     Operand::A16,  10, NONE),  // 0xF2
+    op(   "DI",         Operand::None,  4, NONE),  // 0xF3
+    op(   "CP a16",     Operand::A16,  11, NONE),  // 0xF4
+    op(   "PUSH PSW",   Operand::None, 11, NONE),  // 0xF5
+    op(   "ORI d8",     Operand::D8,    7, ALL),   // 0xF6
+    op(   "RST 6",      Operand::None, 11, NONE),  // 0xF7
+    op(   "RM",         Operand::None,  5, NONE),  // 0xF8
+    op(   "SPHL",       Operand::None,  5, NONE),  // 0xF9
+    op(   "JM a16",     Operand::A16,  10, NONE),  // 0xFA
+    op(   "EI",         Operand::None,  4, NONE),  // 0xFB
+    op(   "CM a16",     Operand::A16,  11, NONE),  // 0xFC
+    alias("CALL a16",   Operand::A16,  17, NONE),  // 0xFD
+    op(   "CPI d8",     Operand::D8,    7, ALL),   // 0xFE
+    op(   "RST 7",      Operand::None, 11, NONE),  // 0xFF
+];
@@ -1,7 +1,135 @@
+use crate::ports::IoDevice;
+use crate::dip_switches::{DipSwitches, DIP_SWITCH_MASK};
+use crate::timing::CYCLES_PER_FRAME;
+
+pub const INPUT_PORTS: [u8; 3] = [0x00, 0x01, 0x02];
+
+const COIN_BIT: u8 = 0x01; // Port 1, active low
+
+// The game polls the coin switch once a frame, so a dropped coin holds it closed for several
+// frames and then leaves it open as long again before the next coin can register
+const COIN_PULSE_CYCLES: u64 = 6 * CYCLES_PER_FRAME as u64;
+
+// The cabinet controls frontends can drive. Games without a control simply leave it unwired.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Control {
+    P1Left,
+    P1Right,
+    P1Up,
+    P1Down,
+    P1Fire,
+    P1Start,
+    P2Left,
+    P2Right,
+    P2Up,
+    P2Down,
+    P2Fire,
+    P2Start,
+    Coin,
+    Tilt,
+}
+
+// One switch wired to a bit of an input port
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputBit {
+    pub control: Control,
+    pub port: u8,
+    pub mask: u8,
+    pub active_low: bool,
+}
+
+impl InputBit {
+    const fn high(control: Control, port: u8, mask: u8) -> Self {
+        Self { control, port, mask, active_low: false }
+    }
+
+    const fn low(control: Control, port: u8, mask: u8) -> Self {
+        Self { control, port, mask, active_low: true }
+    }
+}
+
+// How a game's input ports are wired: each port with the value it reads with nothing pressed,
+// the switch behind each control (a control may drive several bits) and the port holding the
+// operator DIP switches, if the game uses the Space Invaders ones
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputLayout {
+    pub ports: Vec<(u8, u8)>,
+    pub bits: Vec<InputBit>,
+    pub dip_port: Option<u8>,
+}
+
+impl InputLayout {
+    // Player 1 shares its controls between port 0 and port 1, which the game reads in attract
+    // and play respectively. Player 2 is on port 2 alongside the DIP switches.
+    pub fn space_invaders() -> Self {
+        Self {
+            ports: vec![(0x00, 0x0E), (0x01, 0x08 | COIN_BIT), (0x02, 0x00)],
+            bits: vec![
+                InputBit::low(Control::Coin, 0x01, COIN_BIT),
+                InputBit::high(Control::P2Start, 0x01, 0x02),
+                InputBit::high(Control::P1Start, 0x01, 0x04),
+                InputBit::high(Control::P1Fire, 0x00, 0x10),
+                InputBit::high(Control::P1Fire, 0x01, 0x10),
+                InputBit::high(Control::P1Left, 0x00, 0x20),
+                InputBit::high(Control::P1Left, 0x01, 0x20),
+                InputBit::high(Control::P1Right, 0x00, 0x40),
+                InputBit::high(Control::P1Right, 0x01, 0x40),
+                InputBit::high(Control::Tilt, 0x02, 0x04),
+                InputBit::high(Control::P2Fire, 0x02, 0x10),
+                InputBit::high(Control::P2Left, 0x02, 0x20),
+                InputBit::high(Control::P2Right, 0x02, 0x40),
+            ],
+            dip_port: Some(0x02),
+        }
+    }
+
+    // Gun Fight and Boot Hill: a four way stick per player, active low on ports 0 and 1, with
+    // the coin switch on port 2. There is no start button, the coin starts the game. The gun
+    // aim lever on bits 4-6 is left level.
+    pub fn gun_fight() -> Self {
+        Self {
+            ports: vec![(0x00, 0xFF), (0x01, 0xFF), (0x02, 0xFF)],
+            bits: vec![
+                InputBit::low(Control::P1Up, 0x00, 0x01),
+                InputBit::low(Control::P1Down, 0x00, 0x02),
+                InputBit::low(Control::P1Left, 0x00, 0x04),
+                InputBit::low(Control::P1Right, 0x00, 0x08),
+                InputBit::low(Control::P1Fire, 0x00, 0x80),
+                InputBit::low(Control::P2Up, 0x01, 0x01),
+                InputBit::low(Control::P2Down, 0x01, 0x02),
+                InputBit::low(Control::P2Left, 0x01, 0x04),
+                InputBit::low(Control::P2Right, 0x01, 0x08),
+                InputBit::low(Control::P2Fire, 0x01, 0x80),
+                InputBit::low(Control::Coin, 0x02, 0x40),
+            ],
+            dip_port: None,
+        }
+    }
+
+    // Sea Wolf aims with an analog periscope on port 1, which is left centred here
+    pub fn sea_wolf() -> Self {
+        Self {
+            ports: vec![(0x01, 0x10), (0x02, 0x00)],
+            bits: vec![
+                InputBit::high(Control::P1Fire, 0x01, 0x20),
+                InputBit::high(Control::P1Start, 0x02, 0x08),
+                InputBit::high(Control::Coin, 0x02, 0x40),
+            ],
+            dip_port: None,
+        }
+    }
+
+    pub fn read_ports(&self) -> Vec<u8> {
+        self.ports.iter().map(|&(port, _)| port).collect()
+    }
+}
+
 pub struct Inputs {
-    pub port0: u8,
-    pub port1: u8,
-    pub port2: u8
+    layout: InputLayout,
+    values: Vec<u8>, // Current value of each port in layout.ports
+    queued_coins: u32,
+    coin_timer: u64,
+    coin_closed: bool,
 }
 
 pub enum ButtonState {Pressed, Released}
@@ -14,66 +142,242 @@ impl Default for Inputs {
 
 impl Inputs {
     pub fn new() -> Self {
-        Self {
-            port0: 0x0E,
-            port1: 0x08,
-            port2: 0x08,
+        Self::with_dip_switches(DipSwitches::default())
+    }
+
+    pub fn with_dip_switches(dip_switches: DipSwitches) -> Self {
+        Self::with_layout(InputLayout::space_invaders(), dip_switches)
+    }
+
+    pub fn with_layout(layout: InputLayout, dip_switches: DipSwitches) -> Self {
+        let values = layout.ports.iter().map(|&(_, idle)| idle).collect();
+        let mut inputs = Self {
+            layout,
+            values,
+            queued_coins: 0,
+            coin_timer: 0,
+            coin_closed: false,
+        };
+        inputs.set_dip_switches(dip_switches);
+        inputs
+    }
+
+    pub fn set_dip_switches(&mut self, dip_switches: DipSwitches) {
+        if let Some(index) = self.layout.dip_port.and_then(|port| self.index_of(port)) {
+            self.values[index] = (self.values[index] & !DIP_SWITCH_MASK) | dip_switches.port2_bits();
         }
     }
 
-    pub fn player1_start(&mut self, state: ButtonState) {
-        match state {
-            ButtonState::Pressed => self.port1 |= 0x04,
-            ButtonState::Released => self.port1 &= !0x04,
+    fn index_of(&self, port: u8) -> Option<usize> {
+        self.layout.ports.iter().position(|&(p, _)| p == port)
+    }
+
+    // Closes or opens every switch wired to the control
+    pub fn set(&mut self, control: Control, state: ButtonState) {
+        let pressed = matches!(state, ButtonState::Pressed);
+        for bit in self.layout.bits.iter().filter(|b| b.control == control) {
+            let Some(index) = self.index_of(bit.port) else {
+                continue
+            };
+            if pressed != bit.active_low {
+                self.values[index] |= bit.mask;
+            } else {
+                self.values[index] &= !bit.mask;
+            }
         }
     }
 
+    pub fn player1_start(&mut self, state: ButtonState) {
+        self.set(Control::P1Start, state);
+    }
+
     pub fn player1_fire(&mut self, state: ButtonState) {
-        match state {
-            ButtonState::Pressed => self.port1 |= 0x10,
-            ButtonState::Released => self.port1 &= !0x10,
-        }
+        self.set(Control::P1Fire, state);
     }
 
     pub fn player1_left(&mut self, state: ButtonState) {
-        match state {
-            ButtonState::Pressed => self.port1 |= 0x20,
-            ButtonState::Released => self.port1 &= !0x20,
-        }
+        self.set(Control::P1Left, state);
     }
 
     pub fn player1_right(&mut self, state: ButtonState) {
-        match state {
-            ButtonState::Pressed => self.port1 |= 0x40,
-            ButtonState::Released => self.port1 &= !0x40,
-        }
+        self.set(Control::P1Right, state);
+    }
+
+    pub fn player1_up(&mut self, state: ButtonState) {
+        self.set(Control::P1Up, state);
+    }
+
+    pub fn player1_down(&mut self, state: ButtonState) {
+        self.set(Control::P1Down, state);
     }
 
     pub fn player2_start(&mut self, state: ButtonState) {
-        match state {
-            ButtonState::Pressed => self.port1 |= 0x02,
-            ButtonState::Released => self.port1 &= !0x02,
-        }
+        self.set(Control::P2Start, state);
     }
 
     pub fn player2_fire(&mut self, state: ButtonState) {
-        match state {
-            ButtonState::Pressed => self.port2 |= 0x10,
-            ButtonState::Released => self.port2 &= !0x10,
-        }
+        self.set(Control::P2Fire, state);
     }
 
     pub fn player2_left(&mut self, state: ButtonState) {
-        match state {
-            ButtonState::Pressed => self.port2 |= 0x20,
-            ButtonState::Released => self.port2 &= !0x20,
-        }
+        self.set(Control::P2Left, state);
     }
 
     pub fn player2_right(&mut self, state: ButtonState) {
-        match state {
-            ButtonState::Pressed => self.port2 |= 0x40,
-            ButtonState::Released => self.port2 &= !0x40,
+        self.set(Control::P2Right, state);
+    }
+
+    pub fn player2_up(&mut self, state: ButtonState) {
+        self.set(Control::P2Up, state);
+    }
+
+    pub fn player2_down(&mut self, state: ButtonState) {
+        self.set(Control::P2Down, state);
+    }
+
+    // Holds the coin switch directly, for frontends that map it to a key
+    pub fn coin(&mut self, state: ButtonState) {
+        self.set(Control::Coin, state);
+    }
+
+    // Queues a coin drop, played out as a timed switch pulse by elapse()
+    pub fn insert_coin(&mut self) {
+        self.queued_coins += 1;
+    }
+
+    pub fn tilt(&mut self, state: ButtonState) {
+        self.set(Control::Tilt, state);
+    }
+
+    // Advances queued coin pulses by the cycles the CPU just ran
+    pub fn elapse(&mut self, cycles: u32) {
+        if self.coin_timer > 0 {
+            self.coin_timer = self.coin_timer.saturating_sub(cycles as u64);
+            if self.coin_timer == 0 && self.coin_closed {
+                self.coin(ButtonState::Released);
+                self.coin_closed = false;
+                self.coin_timer = COIN_PULSE_CYCLES;
+            }
+        } else if self.queued_coins > 0 {
+            self.queued_coins -= 1;
+            self.coin(ButtonState::Pressed);
+            self.coin_closed = true;
+            self.coin_timer = COIN_PULSE_CYCLES;
+        }
+    }
+}
+
+impl IoDevice for Inputs {
+    fn read(&mut self, port: u8) -> u8 {
+        match self.index_of(port) {
+            Some(index) => self.values[index],
+            None => 0x00,
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dip_switches::{Ships, BonusLife};
+
+    fn port2_for(settings: &str) -> u8 {
+        let mut inputs = Inputs::with_dip_switches(settings.parse().unwrap());
+        inputs.read(0x02)
+    }
+
+    #[test]
+    fn default_dip_switches() {
+        assert_eq!(Inputs::new().read(0x02), 0x08);
+        assert_eq!(port2_for(""), 0x08);
+    }
+
+    #[test]
+    fn dip_switch_ships() {
+        assert_eq!(port2_for("ships=3") & 0x03, 0x00);
+        assert_eq!(port2_for("ships=4") & 0x03, 0x01);
+        assert_eq!(port2_for("ships=5") & 0x03, 0x02);
+        assert_eq!(port2_for("ships=6") & 0x03, 0x03);
+    }
+
+    #[test]
+    fn dip_switch_bonus_life() {
+        assert_eq!(port2_for("bonus=1000") & 0x08, 0x08);
+        assert_eq!(port2_for("bonus=1500") & 0x08, 0x00);
+    }
+
+    #[test]
+    fn dip_switch_coin_info() {
+        assert_eq!(port2_for("coin-info=on") & 0x80, 0x00);
+        assert_eq!(port2_for("coin-info=off") & 0x80, 0x80);
+    }
+
+    #[test]
+    fn set_dip_switches_keeps_player_inputs() {
+        let mut inputs = Inputs::new();
+        inputs.player2_fire(ButtonState::Pressed);
+        inputs.set_dip_switches(DipSwitches { ships: Ships::Six, bonus_life: BonusLife::At1500, coin_info: false });
+        assert_eq!(inputs.read(0x02), 0x93);
+    }
+
+    #[test]
+    fn invalid_dip_switches() {
+        assert!("ships=7".parse::<DipSwitches>().is_err());
+        assert!("bonus".parse::<DipSwitches>().is_err());
+        assert!("lives=3".parse::<DipSwitches>().is_err());
+    }
+
+    #[test]
+    fn port0_and_player1_controls() {
+        let mut inputs = Inputs::new();
+        assert_eq!(inputs.read(0x00), 0x0E);
+        inputs.player1_left(ButtonState::Pressed);
+        assert_eq!(inputs.read(0x00), 0x2E);
+        assert_eq!(inputs.read(0x01), 0x29);
+        inputs.player1_left(ButtonState::Released);
+        assert_eq!(inputs.read(0x00), 0x0E);
+    }
+
+    #[test]
+    fn coin_pulse() {
+        let mut inputs = Inputs::new();
+        assert_eq!(inputs.read(0x01) & COIN_BIT, COIN_BIT);
+        inputs.insert_coin();
+        inputs.insert_coin();
+        let mut closed_frames = Vec::new();
+        for frame in 0..30 {
+            inputs.elapse(CYCLES_PER_FRAME);
+            if inputs.read(0x01) & COIN_BIT == 0 {
+                closed_frames.push(frame);
+            }
+        }
+        assert_eq!(closed_frames, vec![0, 1, 2, 3, 4, 5, 13, 14, 15, 16, 17, 18]);
+    }
+
+    #[test]
+    fn tilt() {
+        let mut inputs = Inputs::new();
+        inputs.tilt(ButtonState::Pressed);
+        assert_eq!(inputs.read(0x02), 0x0C);
+        inputs.tilt(ButtonState::Released);
+        assert_eq!(inputs.read(0x02), 0x08);
+    }
+
+    #[test]
+    fn active_low_layout() {
+        let mut inputs = Inputs::with_layout(InputLayout::gun_fight(), DipSwitches::default());
+        assert_eq!(inputs.read(0x02), 0xFF); // No DIP switches on the Space Invaders bits
+        inputs.player1_fire(ButtonState::Pressed);
+        inputs.player2_left(ButtonState::Pressed);
+        inputs.player1_start(ButtonState::Pressed); // Not wired
+        assert_eq!(inputs.read(0x00), 0x7F);
+        assert_eq!(inputs.read(0x01), 0xFB);
+        inputs.player1_fire(ButtonState::Released);
+        assert_eq!(inputs.read(0x00), 0xFF);
+        assert_eq!(inputs.read(0x03), 0x00);
+        inputs.player1_up(ButtonState::Pressed);
+        inputs.player2_down(ButtonState::Pressed);
+        assert_eq!(inputs.read(0x00), 0xFE);
+        assert_eq!(inputs.read(0x01), 0xF9);
+    }
+}
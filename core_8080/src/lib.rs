@@ -1,16 +1,49 @@
 mod registers;
 mod memory;
+mod bus;
 mod shift_register;
 mod condition_flags;
 mod io;
+mod dip_switches;
 mod core_error;
+mod ports;
+mod watchdog;
+mod sound;
+mod timing;
+mod instructions;
+mod rom_set;
+mod rom_database;
+mod machine_config;
+mod machine;
+mod framebuffer;
+mod overlay;
+#[cfg(test)]
+mod cpm;
 
-use io::Inputs;
-use shift_register::ShiftRegister;
 use registers::Registers;
 use memory::Memory;
 use condition_flags::ConditionFlags;
-use core_error::CoreError;
+use timing::{BRANCH_TAKEN_CYCLES, HALTED_CYCLES};
+
+pub use timing::{CPU_CLOCK_HZ, FRAMES_PER_SECOND, CYCLES_PER_FRAME, SCANLINES_PER_FRAME, MID_SCREEN_LINE, VBLANK_LINE};
+
+pub use instructions::{Instruction, Operand, INSTRUCTIONS};
+pub use memory::AddressWrap;
+pub use bus::{Bus, FlatMemory, MemoryMap, Region};
+pub use rom_set::{RomSet, RomPart, RomSource};
+pub use rom_database::{RomDatabase, KnownRom, Variant};
+pub use machine::{Machine, FrameSummary, FrameEvent};
+pub use overlay::{ColourOverlay, Band, Rgb};
+pub use framebuffer::{Framebuffer, PixelFormat, SCREEN_WIDTH, SCREEN_HEIGHT, VRAM_START, VRAM_SIZE};
+pub use machine_config::{MachineConfig, Game, MemoryLayout, PortMap, SoundBoard, Overlay};
+pub use core_error::CoreError;
+pub use ports::{IoDevice, Ports, SharedDevice};
+pub use watchdog::{Watchdog, WATCHDOG_PORT};
+pub use sound::{SoundLatches, Sound, SoundEvent, SOUND_PORT_1, SOUND_PORT_2};
+pub use io::{Inputs, ButtonState, Control, InputBit, InputLayout, INPUT_PORTS};
+pub use dip_switches::{DipSwitches, Ships, BonusLife};
+pub use shift_register::{ShiftRegister, ShifterPorts, SHIFT_OFFSET_PORT, SHIFT_RESULT_PORT, SHIFT_DATA_PORT};
+pub use condition_flags::{SIGN_BIT, ZERO_BIT, AUX_CARRY_BIT, PARITY_BIT, CARRY_BIT};
 
 const SR_0_ADDR: u16 = 0x0000;
 const SR_1_ADDR: u16 = 0x0008;
@@ -22,14 +55,19 @@ const SR_6_ADDR: u16 = 0x0030;
 const SR_7_ADDR: u16 = 0x0038;
 
 const ROM_ADDR: u16 = 0x0000;
+const ROM_SIZE: usize = 0x2000;
 
 pub struct CPU {
     memory: Memory,
     registers: Registers,
     flags: ConditionFlags,
-    shifter: ShiftRegister,
     interrupt_enable: bool,
-    pub input: Inputs,
+    interrupt_delay: bool,
+    pending_interrupt: Option<u8>,
+    halted: bool,
+    undocumented_opcodes: bool,
+    ports: Ports,
+    cycles: u64,
 }
 
 impl Default for CPU {
@@ -40,57 +78,230 @@ impl Default for CPU {
 
 impl CPU {
     pub fn new() -> Self {
+        Self::with_bus(Box::new(FlatMemory::new()))
+    }
+
+    // CPU that reads and writes through a machine's memory map, e.g. MemoryMap::space_invaders()
+    pub fn with_bus(bus: Box<dyn Bus>) -> Self {
         Self {
-            memory: Memory::new(),
+            memory: Memory::with_bus(bus),
             registers: Registers::new(),
             flags: ConditionFlags::new(),
             interrupt_enable: false,
-            shifter: ShiftRegister::new(),
-            input: Inputs::new(),
+            interrupt_delay: false,
+            pending_interrupt: None,
+            halted: false,
+            undocumented_opcodes: false,
+            ports: Ports::new(),
+            cycles: 0,
         }
     }
 
     pub fn load_rom(&mut self, buffer: &[u8]) -> Result<(), CoreError> {
+        if buffer.len() > ROM_SIZE {
+            return Err(CoreError::RomSizeError)
+        }
         self.memory.load_rom(buffer, ROM_ADDR)
     }
 
+    // Loads an image of any size at an arbitrary address and starts execution there
+    pub fn load_rom_at(&mut self, buffer: &[u8], start_addr: u16) -> Result<(), CoreError> {
+        self.memory.load_rom(buffer, start_addr)
+    }
+
+    // Pulses the RESET line: execution restarts at 0x0000 with interrupts disabled. Registers,
+    // flags and memory keep their contents as on real hardware.
+    pub fn reset(&mut self) {
+        self.memory.program_counter = 0x0000;
+        self.interrupt_enable = false;
+        self.interrupt_delay = false;
+        self.pending_interrupt = None;
+        self.halted = false;
+    }
+
     pub fn tick(&mut self) -> Result<u32, CoreError> {
+        let cycles = self.step()?;
+        self.cycles += cycles as u64;
+        Ok(cycles)
+    }
+
+    // T-states executed since power on, used to timestamp port writes
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    fn step(&mut self) -> Result<u32, CoreError> {
+        if let Some(opcode) = self.pending_interrupt {
+            if self.interrupt_enable && !self.interrupt_delay {
+                // The opcode comes from the data bus instead of memory, so PC is not advanced
+                self.pending_interrupt = None;
+                self.interrupt_enable = false;
+                self.halted = false;
+                return self.execute(opcode)
+            }
+        }
+        self.interrupt_delay = false;
+        if self.halted {
+            return Ok(HALTED_CYCLES)
+        }
         let opcode = self.memory.fetch_byte()?;
         //println!("{:#04x}", opcode);
         self.execute(opcode)
     }
 
-    pub fn interrupt(&mut self, interrupt: u8) {
-        let _ = match interrupt {
-            0 => self.execute(0xC7),
-            1 => self.execute(0xCF),
-            2 => self.execute(0xD7),
-            3 => self.execute(0xDF),
-            4 => self.execute(0xE7),
-            5 => self.execute(0xEF),
-            6 => self.execute(0xF7),
-            7 => self.execute(0xFF),
-            _ => Ok(0)
-        };
+    // True after HLT until the next interrupt, while tick() only burns cycles
+    pub fn is_halted(&self) -> bool {
+        self.halted
     }
 
-    fn generate_psw(&self) -> u16 { // Could break apart save and restore to seperate Registers and ConditionFlags methods
-        let data_l = (self.flags.zero as u8) |
-            (self.flags.sign as u8) << 1 |
-            (self.flags.parity as u8) << 2 |
-            (self.flags.carry as u8) << 3;
-        (self.registers.a_reg as u16) << 8 | (data_l as u16)
+    // When enabled, undocumented opcodes run as the NOP, JMP, RET and CALL they alias on real
+    // silicon. Otherwise (the default) they are reported as OpcodeError to catch runaway code.
+    pub fn set_undocumented_opcodes(&mut self, enabled: bool) {
+        self.undocumented_opcodes = enabled;
+    }
+
+    // Diagnostic mode that records PC, SP and word accesses wrapping past 0xFFFF
+    pub fn set_wrap_reporting(&mut self, enabled: bool) {
+        self.memory.set_wrap_reporting(enabled);
+    }
+
+    pub fn take_address_wraps(&mut self) -> Vec<AddressWrap> {
+        self.memory.take_wraps()
+    }
+
+    // Reads through the bus without executing anything, for debuggers and video decoding
+    pub fn read_memory(&mut self, address: u16) -> Result<u8, CoreError> {
+        self.memory.read_byte(address)
+    }
+
+    // Port devices used by IN and OUT, registered by the machine
+    pub fn ports_mut(&mut self) -> &mut Ports {
+        &mut self.ports
+    }
+
+    pub fn interrupts_enabled(&self) -> bool {
+        self.interrupt_enable
+    }
+
+    // Raises the interrupt line with `opcode` on the data bus, usually an RST from rst_opcode().
+    // The request stays latched until the CPU accepts it at an instruction boundary with INTE set,
+    // and a newer request replaces one that is still pending.
+    pub fn interrupt(&mut self, opcode: u8) {
+        self.pending_interrupt = Some(opcode);
+    }
+
+    pub fn clear_interrupt(&mut self) {
+        self.pending_interrupt = None;
+    }
+
+    fn generate_psw(&self) -> u16 {
+        (self.registers.a_reg as u16) << 8 | (self.flags.get_byte() as u16)
     }
 
     fn restore_psw(&mut self, psw: u16) {
         self.registers.a_reg = ((psw & 0xFF00) >> 8) as u8;
-        self.flags.zero = (psw & 0x01) == 0x01;
-        self.flags.parity = (psw & 0x02) == 0x02;
-        self.flags.carry = (psw & 0x04) == 0x04;
+        self.flags.set_byte((psw & 0x00FF) as u8);
+    }
+
+    fn set_result_flags(&mut self, result: u8) {
+        self.flags.zero = result == 0;
+        self.flags.sign = result & 0x80 != 0;
+        self.flags.parity = parity(result);
+    }
+
+    fn add(&mut self, data: u8, carry_in: bool) {
+        let result = (self.registers.a_reg as u16) + (data as u16) + (carry_in as u16);
+        self.flags.aux_carry = (self.registers.a_reg & 0x0F) + (data & 0x0F) + (carry_in as u8) > 0x0F;
+        self.registers.a_reg = result as u8;
+        self.flags.carry = result > 0xFF;
+        self.set_result_flags(self.registers.a_reg);
+    }
+
+    fn subtract(&mut self, data: u8, borrow_in: bool) {
+        self.registers.a_reg = self.sub_flags(data, borrow_in);
+    }
+
+    fn compare(&mut self, data: u8) {
+        self.sub_flags(data, false);
+    }
+
+    // Sets the flags for A - data - borrow and returns the result without storing it
+    fn sub_flags(&mut self, data: u8, borrow_in: bool) -> u8 {
+        let subtrahend = (data as u16) + (borrow_in as u16);
+        let result = (self.registers.a_reg as u16).wrapping_sub(subtrahend) as u8;
+        // The ALU subtracts by adding the complement, so AC is the carry out of that low nibble add
+        self.flags.aux_carry = (self.registers.a_reg & 0x0F) + (!data & 0x0F) + (!borrow_in as u8) > 0x0F;
+        self.flags.carry = (self.registers.a_reg as u16) < subtrahend;
+        self.set_result_flags(result);
+        result
+    }
+
+    fn and(&mut self, data: u8) {
+        self.flags.aux_carry = (self.registers.a_reg | data) & 0x08 != 0;
+        self.registers.a_reg &= data;
+        self.flags.carry = false;
+        self.set_result_flags(self.registers.a_reg);
+    }
+
+    fn xor(&mut self, data: u8) {
+        self.registers.a_reg ^= data;
+        self.flags.carry = false;
+        self.flags.aux_carry = false;
+        self.set_result_flags(self.registers.a_reg);
+    }
+
+    fn or(&mut self, data: u8) {
+        self.registers.a_reg |= data;
+        self.flags.carry = false;
+        self.flags.aux_carry = false;
+        self.set_result_flags(self.registers.a_reg);
+    }
+
+    fn increment(&mut self, data: u8) -> u8 {
+        let result = data.wrapping_add(1);
+        self.flags.aux_carry = result & 0x0F == 0x00;
+        self.set_result_flags(result);
+        result
+    }
+
+    fn decrement(&mut self, data: u8) -> u8 {
+        let result = data.wrapping_sub(1);
+        self.flags.aux_carry = result & 0x0F != 0x0F;
+        self.set_result_flags(result);
+        result
+    }
+
+    fn jump_if(&mut self, condition: bool) -> Result<(), CoreError> {
+        let addr = self.memory.fetch_two_bytes()?;
+        if condition {
+            self.memory.program_counter = addr;
+        }
+        Ok(())
+    }
+
+    // Returns the extra T-states spent when the call is taken
+    fn call_if(&mut self, condition: bool) -> Result<u32, CoreError> {
+        let addr = self.memory.fetch_two_bytes()?;
+        if !condition {
+            return Ok(0)
+        }
+        self.memory.push_stack(self.memory.program_counter)?; // Push return address to stack
+        self.memory.program_counter = addr;
+        Ok(BRANCH_TAKEN_CYCLES)
+    }
+
+    // Returns the extra T-states spent when the return is taken
+    fn return_if(&mut self, condition: bool) -> Result<u32, CoreError> {
+        if !condition {
+            return Ok(0)
+        }
+        self.memory.program_counter = self.memory.pop_stack()?;
+        Ok(BRANCH_TAKEN_CYCLES)
     }
 
     fn execute(&mut self, opcode: u8) -> Result<u32, CoreError> {
-        let mut cycles = 1;
+        let mut cycles = INSTRUCTIONS[opcode as usize].cycles;
+        let opcode = if self.undocumented_opcodes { documented_alias(opcode) } else { opcode };
         
         // Super big and ugly match statement because I'm not sure of a better way
         match opcode {
@@ -98,93 +309,73 @@ impl CPU {
             // *** Loads ***
             0x01 => { // LXI B,d16
                 self.registers.bc_reg.set_pair(self.memory.fetch_two_bytes()?);
-                cycles = 3;
             },
             0x11 => { // LXI D,d16
                 self.registers.de_reg.set_pair(self.memory.fetch_two_bytes()?);
-                cycles = 3;
             },
             0x21 => { // LXI H,d16
                 self.registers.hl_reg.set_pair(self.memory.fetch_two_bytes()?);
-                cycles = 3;
             },
             0x31 => { // LXI SP,d16
                 self.memory.stack_pointer = self.memory.fetch_two_bytes()?;
-                cycles = 3;
             },
             0x0A => { // LDAX B
                 let addr = self.registers.bc_reg.get_pair();
                 self.registers.a_reg = self.memory.read_byte(addr)?;
-                cycles = 2;
             },
             0x1A => { // LDAX D
                 let addr = self.registers.de_reg.get_pair();
                 self.registers.a_reg = self.memory.read_byte(addr)?;
-                cycles = 2;
             },
             0x2A => { // LHLD a16
                 let addr = self.memory.fetch_two_bytes()?;
                 self.registers.hl_reg.set_pair(self.memory.read_two_bytes(addr)?);
-                cycles = 5;
             },
             0x3A => { // LDA a16
                 let addr = self.memory.fetch_two_bytes()?;
                 self.registers.a_reg = self.memory.read_byte(addr)?;
-                cycles = 4;
             },
             0x06 => { // MVI B,d8
                 self.registers.bc_reg.high = self.memory.fetch_byte()?;
-                cycles = 2;
                 },
             0x0E => { // MVI C,d8
                 self.registers.bc_reg.low = self.memory.fetch_byte()?;
-                cycles = 2;
             }
             0x16 => { // MVI D,d8
                 self.registers.de_reg.high = self.memory.fetch_byte()?;
-                cycles = 2;
                 },
             0x1E => { // MVI E,d8
                 self.registers.de_reg.low = self.memory.fetch_byte()?;
-                cycles = 2;
             },
             0x26 => { // MVI H,d8
                 self.registers.hl_reg.high = self.memory.fetch_byte()?;
-                cycles = 2;
             },
             0x2E => { // MVI L,d8
                 self.registers.hl_reg.low = self.memory.fetch_byte()?;
-                cycles = 2;
             },
             0x36 => { // MVI M,d8
                 let data = self.memory.fetch_byte()?;
                 let addr = self.registers.hl_reg.get_pair();
                 self.memory.write_byte(addr, data)?;
-                cycles = 3;
             },
             0x3E => { // MVI A,d8
                 self.registers.a_reg = self.memory.fetch_byte()?;
-                cycles = 2;
             },
 
             // *** Stores ***
             0x02 => { // STAX B
                 self.memory.write_byte(self.registers.bc_reg.get_pair(), self.registers.a_reg)?;
-                cycles = 2;
             },
             0x12 => { // STAX D
                 self.memory.write_byte(self.registers.de_reg.get_pair(), self.registers.a_reg)?;
-                cycles = 2;
             },
             0x22 => { // SHLD a16
                 let addr = self.memory.fetch_two_bytes()?;
                 self.memory.write_two_bytes(addr, self.registers.hl_reg.get_pair())?;
-                cycles = 5;
             },
             0x32 => { // STA a16
                 let addr = self.memory.fetch_two_bytes()?;
                 self.memory.write_byte(addr, self.registers.a_reg)?;
-                cycles = 4;
             },
 
             // *** Moves ***
@@ -206,7 +397,6 @@ impl CPU {
             },
             0x46 => { // MOV B,M
                 self.registers.bc_reg.high = self.memory.read_byte(self.registers.hl_reg.get_pair())?;
-                cycles = 2;
             },
             0x47 => { // MOV B,A
                 self.registers.bc_reg.high = self.registers.a_reg;
@@ -229,7 +419,6 @@ impl CPU {
             },
             0x4E => { // MOV C,M
                 self.registers.bc_reg.low = self.memory.read_byte(self.registers.hl_reg.get_pair())?;
-                cycles = 2;
             },
             0x4F => { // MOV C,A
                 self.registers.bc_reg.low = self.registers.a_reg;
@@ -252,7 +441,6 @@ impl CPU {
             },
             0x56 => { // MOV D,M
                 self.registers.de_reg.high = self.memory.read_byte(self.registers.hl_reg.get_pair())?;
-                cycles = 2;
             },
             0x57 => { // MOV D,A
                 self.registers.de_reg.high = self.registers.a_reg;
@@ -275,7 +463,6 @@ impl CPU {
             },
             0x5E => { // MOV E,M
                 self.registers.de_reg.low = self.memory.read_byte(self.registers.hl_reg.get_pair())?;
-                cycles = 2;
             },
             0x5F => { // MOV E,A
                 self.registers.de_reg.low = self.registers.a_reg;
@@ -298,7 +485,6 @@ impl CPU {
             },
             0x66 => { // MOV H,M
                 self.registers.hl_reg.high = self.memory.read_byte(self.registers.hl_reg.get_pair())?;
-                cycles = 2;
             },
             0x67 => { // MOV H,A
                 self.registers.hl_reg.high = self.registers.a_reg;
@@ -313,7 +499,7 @@ impl CPU {
                 self.registers.hl_reg.low = self.registers.de_reg.high;
             },
             0x6B => { // MOV L,E
-                self.registers.hl_reg.low = self.registers.bc_reg.low;
+                self.registers.hl_reg.low = self.registers.de_reg.low;
             },
             0x6C => { // MOV L,H
                 self.registers.hl_reg.low = self.registers.hl_reg.high;
@@ -321,7 +507,6 @@ impl CPU {
             0x6D => (), // MOV L,L
             0x6E => { // MOV L,M
                 self.registers.hl_reg.low = self.memory.read_byte(self.registers.hl_reg.get_pair())?;
-                cycles = 2;
             },
             0x6F => { // MOV L,A
                 self.registers.hl_reg.low = self.registers.a_reg;
@@ -329,37 +514,30 @@ impl CPU {
             0x70 => { // MOV M,B
                 let addr = self.registers.hl_reg.get_pair();
                 self.memory.write_byte(addr, self.registers.bc_reg.high)?;
-                cycles = 2;
             },
             0x71 => { // MOV M,C
                 let addr = self.registers.hl_reg.get_pair();
                 self.memory.write_byte(addr, self.registers.bc_reg.low)?;
-                cycles = 2;
             },
             0x72 => { // MOV M,D
                 let addr = self.registers.hl_reg.get_pair();
                 self.memory.write_byte(addr, self.registers.de_reg.high)?;
-                cycles = 2;
             },
             0x73 => { // MOV M,E
                 let addr = self.registers.hl_reg.get_pair();
                 self.memory.write_byte(addr, self.registers.de_reg.low)?;
-                cycles = 2;
             },
             0x74 => { // MOV M,H
                 let addr = self.registers.hl_reg.get_pair();
                 self.memory.write_byte(addr, self.registers.hl_reg.high)?;
-                cycles = 2;
             },
             0x75 => { // MOV M,L
                 let addr = self.registers.hl_reg.get_pair();
                 self.memory.write_byte(addr, self.registers.hl_reg.low)?;
-                cycles = 2;
             },
             0x77 => { // MOV M,A
                 let addr = self.registers.hl_reg.get_pair();
                 self.memory.write_byte(addr, self.registers.a_reg)?;
-                cycles = 2;
             },
             0x78 => { // MOV A,B
                 self.registers.a_reg = self.registers.bc_reg.high;
@@ -382,7 +560,6 @@ impl CPU {
             0x7E => { // MOV A,M
                 let addr = self.registers.hl_reg.get_pair();
                 self.registers.a_reg = self.memory.read_byte(addr)?;
-                cycles = 2;
             },
             0x7F => (), // MOV A,A
 
@@ -401,110 +578,60 @@ impl CPU {
                 self.memory.stack_pointer = self.memory.stack_pointer.wrapping_add(1);
             },
             0x04 => { // INR B
-                self.registers.bc_reg.high = self.registers.bc_reg.high.wrapping_add(1);
-                self.flags.zero = self.registers.bc_reg.high == 0;
-                self.flags.sign = self.registers.bc_reg.high & 0x80 != 0;
-                self.flags.parity = parity(self.registers.bc_reg.high);
+                self.registers.bc_reg.high = self.increment(self.registers.bc_reg.high);
             },
             0x0C => { // INR C
-                self.registers.bc_reg.low = self.registers.bc_reg.low.wrapping_add(1);
-                self.flags.zero = self.registers.bc_reg.low == 0;
-                self.flags.sign = self.registers.bc_reg.low & 0x80 != 0;
-                self.flags.parity = parity(self.registers.bc_reg.low);
+                self.registers.bc_reg.low = self.increment(self.registers.bc_reg.low);
             },
             0x14 => { // INR D
-                self.registers.de_reg.high = self.registers.de_reg.high.wrapping_add(1);
-                self.flags.zero = self.registers.de_reg.high == 0;
-                self.flags.sign = self.registers.de_reg.high & 0x80 != 0;
-                self.flags.parity = parity(self.registers.de_reg.high);
+                self.registers.de_reg.high = self.increment(self.registers.de_reg.high);
             },
             0x1C => { // INR E
-                self.registers.de_reg.low = self.registers.de_reg.low.wrapping_add(1);
-                self.flags.zero = self.registers.de_reg.low == 0;
-                self.flags.sign = self.registers.de_reg.low & 0x80 != 0;
-                self.flags.parity = parity(self.registers.de_reg.low);
+                self.registers.de_reg.low = self.increment(self.registers.de_reg.low);
             },
             0x24 => { // INR H
-                self.registers.hl_reg.high = self.registers.hl_reg.high.wrapping_add(1);
-                self.flags.zero = self.registers.hl_reg.high == 0;
-                self.flags.sign = self.registers.hl_reg.high & 0x80 != 0;
-                self.flags.parity = parity(self.registers.hl_reg.high);
+                self.registers.hl_reg.high = self.increment(self.registers.hl_reg.high);
             },
             0x2C => { // INR L
-                self.registers.hl_reg.low = self.registers.hl_reg.low.wrapping_add(1);
-                self.flags.zero = self.registers.hl_reg.low == 0;
-                self.flags.sign = self.registers.hl_reg.low & 0x80 != 0;
-                self.flags.parity = parity(self.registers.hl_reg.low);
+                self.registers.hl_reg.low = self.increment(self.registers.hl_reg.low);
             },
             0x34 => { // INR M
                 let addr = self.registers.hl_reg.get_pair();
-                let data_old = self.memory.read_byte(addr)?;
-                let data_new = data_old.wrapping_add(1);
-                self.memory.write_byte(addr, data_new)?;
-                self.flags.zero = data_new == 0;
-                self.flags.sign = data_new & 0x80 != 0;
-                self.flags.parity = parity(data_new);
-                cycles = 3;
+                let data = self.memory.read_byte(addr)?;
+                let data = self.increment(data);
+                self.memory.write_byte(addr, data)?;
             },
             0x3C => { // INR A
-                self.registers.a_reg = self.registers.a_reg.wrapping_add(1);
-                self.flags.zero = self.registers.a_reg == 0;
-                self.flags.sign = self.registers.a_reg & 0x80 != 0;
-                self.flags.parity = parity(self.registers.a_reg);
+                self.registers.a_reg = self.increment(self.registers.a_reg);
             },
 
             // *** Decrements ***
             0x05 => { // DCR B
-                self.registers.bc_reg.high = self.registers.bc_reg.high.wrapping_sub(1);
-                self.flags.zero = self.registers.bc_reg.high == 0;
-                self.flags.sign = self.registers.bc_reg.high & 0x80 != 0;
-                self.flags.parity = parity(self.registers.bc_reg.high);
+                self.registers.bc_reg.high = self.decrement(self.registers.bc_reg.high);
             },
             0x0D => { // DCR C
-                self.registers.bc_reg.low = self.registers.bc_reg.low.wrapping_sub(1);
-                self.flags.zero = self.registers.bc_reg.low == 0;
-                self.flags.sign = self.registers.bc_reg.low & 0x80 != 0;
-                self.flags.parity = parity(self.registers.bc_reg.low);
+                self.registers.bc_reg.low = self.decrement(self.registers.bc_reg.low);
             },
             0x15 => { // DCR D
-                self.registers.de_reg.high = self.registers.de_reg.high.wrapping_sub(1);
-                self.flags.zero = self.registers.de_reg.high == 0;
-                self.flags.sign = self.registers.de_reg.high & 0x80 != 0;
-                self.flags.parity = parity(self.registers.de_reg.high);
+                self.registers.de_reg.high = self.decrement(self.registers.de_reg.high);
             },
             0x1D => { // DCR E
-                self.registers.de_reg.low = self.registers.de_reg.low.wrapping_sub(1);
-                self.flags.zero = self.registers.de_reg.low == 0;
-                self.flags.sign = self.registers.de_reg.low & 0x80 != 0;
-                self.flags.parity = parity(self.registers.de_reg.low);
+                self.registers.de_reg.low = self.decrement(self.registers.de_reg.low);
             },
             0x25 => { // DCR H
-                self.registers.hl_reg.high = self.registers.hl_reg.high.wrapping_sub(1);
-                self.flags.zero = self.registers.hl_reg.high == 0;
-                self.flags.sign = self.registers.hl_reg.high & 0x80 != 0;
-                self.flags.parity = parity(self.registers.hl_reg.high);
+                self.registers.hl_reg.high = self.decrement(self.registers.hl_reg.high);
             },
             0x2D => { // DCR L
-                self.registers.hl_reg.low = self.registers.hl_reg.low.wrapping_sub(1);
-                self.flags.zero = self.registers.hl_reg.low == 0;
-                self.flags.sign = self.registers.hl_reg.low & 0x80 != 0;
-                self.flags.parity = parity(self.registers.hl_reg.low);
+                self.registers.hl_reg.low = self.decrement(self.registers.hl_reg.low);
             },
             0x35 => { // DCR M
                 let addr = self.registers.hl_reg.get_pair();
-                let data_old = self.memory.read_byte(addr)?;
-                let data_new = data_old.wrapping_sub(1);
-                self.memory.write_byte(addr, data_new)?;
-                self.flags.zero = data_new == 0;
-                self.flags.sign = data_new & 0x80 != 0;
-                self.flags.parity = parity(data_new);
-                cycles = 3;
+                let data = self.memory.read_byte(addr)?;
+                let data = self.decrement(data);
+                self.memory.write_byte(addr, data)?;
             },
             0x3D => { // DCR A
-                self.registers.a_reg = self.registers.a_reg.wrapping_sub(1);
-                self.flags.zero = self.registers.a_reg == 0;
-                self.flags.sign = self.registers.a_reg & 0x80 != 0;
-                self.flags.parity = parity(self.registers.a_reg);
+                self.registers.a_reg = self.decrement(self.registers.a_reg);
             },
             0x0B => { //DCX B
                 self.registers.bc_reg.set_pair(self.registers.bc_reg.get_pair().wrapping_sub(1));
@@ -524,25 +651,139 @@ impl CPU {
                 let (result, carry) = self.registers.hl_reg.get_pair().overflowing_add(self.registers.bc_reg.get_pair());
                 self.registers.hl_reg.set_pair(result);
                 self.flags.carry = carry;
-                cycles = 3;
             },
             0x19 => { // DAD D
                 let (result, carry) = self.registers.hl_reg.get_pair().overflowing_add(self.registers.de_reg.get_pair());
                 self.registers.hl_reg.set_pair(result);
                 self.flags.carry = carry;
-                cycles = 3;
             },
             0x29 => { // DAD H
                 let (result, carry) = self.registers.hl_reg.get_pair().overflowing_add(self.registers.hl_reg.get_pair());
                 self.registers.hl_reg.set_pair(result);
                 self.flags.carry = carry;
-                cycles = 3;
             },
             0x39 => { // DAD SP
                 let (result, carry) = self.registers.hl_reg.get_pair().overflowing_add(self.memory.stack_pointer);
                 self.registers.hl_reg.set_pair(result);
                 self.flags.carry = carry;
-                cycles = 3;
+            },
+
+            // *** Adds and Subtracts ***
+            0x80 => { // ADD B
+                self.add(self.registers.bc_reg.high, false);
+            },
+            0x81 => { // ADD C
+                self.add(self.registers.bc_reg.low, false);
+            },
+            0x82 => { // ADD D
+                self.add(self.registers.de_reg.high, false);
+            },
+            0x83 => { // ADD E
+                self.add(self.registers.de_reg.low, false);
+            },
+            0x84 => { // ADD H
+                self.add(self.registers.hl_reg.high, false);
+            },
+            0x85 => { // ADD L
+                self.add(self.registers.hl_reg.low, false);
+            },
+            0x86 => { // ADD M
+                let data = self.memory.read_byte(self.registers.hl_reg.get_pair())?;
+                self.add(data, false);
+            },
+            0x87 => { // ADD A
+                self.add(self.registers.a_reg, false);
+            },
+            0x88 => { // ADC B
+                self.add(self.registers.bc_reg.high, self.flags.carry);
+            },
+            0x89 => { // ADC C
+                self.add(self.registers.bc_reg.low, self.flags.carry);
+            },
+            0x8A => { // ADC D
+                self.add(self.registers.de_reg.high, self.flags.carry);
+            },
+            0x8B => { // ADC E
+                self.add(self.registers.de_reg.low, self.flags.carry);
+            },
+            0x8C => { // ADC H
+                self.add(self.registers.hl_reg.high, self.flags.carry);
+            },
+            0x8D => { // ADC L
+                self.add(self.registers.hl_reg.low, self.flags.carry);
+            },
+            0x8E => { // ADC M
+                let data = self.memory.read_byte(self.registers.hl_reg.get_pair())?;
+                self.add(data, self.flags.carry);
+            },
+            0x8F => { // ADC A
+                self.add(self.registers.a_reg, self.flags.carry);
+            },
+            0x90 => { // SUB B
+                self.subtract(self.registers.bc_reg.high, false);
+            },
+            0x91 => { // SUB C
+                self.subtract(self.registers.bc_reg.low, false);
+            },
+            0x92 => { // SUB D
+                self.subtract(self.registers.de_reg.high, false);
+            },
+            0x93 => { // SUB E
+                self.subtract(self.registers.de_reg.low, false);
+            },
+            0x94 => { // SUB H
+                self.subtract(self.registers.hl_reg.high, false);
+            },
+            0x95 => { // SUB L
+                self.subtract(self.registers.hl_reg.low, false);
+            },
+            0x96 => { // SUB M
+                let data = self.memory.read_byte(self.registers.hl_reg.get_pair())?;
+                self.subtract(data, false);
+            },
+            0x97 => { // SUB A
+                self.subtract(self.registers.a_reg, false);
+            },
+            0x98 => { // SBB B
+                self.subtract(self.registers.bc_reg.high, self.flags.carry);
+            },
+            0x99 => { // SBB C
+                self.subtract(self.registers.bc_reg.low, self.flags.carry);
+            },
+            0x9A => { // SBB D
+                self.subtract(self.registers.de_reg.high, self.flags.carry);
+            },
+            0x9B => { // SBB E
+                self.subtract(self.registers.de_reg.low, self.flags.carry);
+            },
+            0x9C => { // SBB H
+                self.subtract(self.registers.hl_reg.high, self.flags.carry);
+            },
+            0x9D => { // SBB L
+                self.subtract(self.registers.hl_reg.low, self.flags.carry);
+            },
+            0x9E => { // SBB M
+                let data = self.memory.read_byte(self.registers.hl_reg.get_pair())?;
+                self.subtract(data, self.flags.carry);
+            },
+            0x9F => { // SBB A
+                self.subtract(self.registers.a_reg, self.flags.carry);
+            },
+            0xC6 => { // ADI d8
+                let data = self.memory.fetch_byte()?;
+                self.add(data, false);
+            },
+            0xCE => { // ACI d8
+                let data = self.memory.fetch_byte()?;
+                self.add(data, self.flags.carry);
+            },
+            0xD6 => { // SUI d8
+                let data = self.memory.fetch_byte()?;
+                self.subtract(data, false);
+            },
+            0xDE => { // SBI d8
+                let data = self.memory.fetch_byte()?;
+                self.subtract(data, self.flags.carry);
             },
 
             // ****** Logic Group ******
@@ -565,24 +806,119 @@ impl CPU {
                 self.flags.carry = temp & 0x01 == 0x01;
             },
             0x27 => { // DAA
-                let mut data_l = self.registers.a_reg & 0x0F;
-                let mut data_h = self.registers.a_reg >> 4;
-                let mut carry = false;
-                if data_l > 9 {
-                    data_l += 6;
-                    if data_l > 0x0F {
-                        data_h += data_l >> 4;
-                    }
+                let low = self.registers.a_reg & 0x0F;
+                let high = self.registers.a_reg >> 4;
+                let mut correction = 0;
+                let mut carry = self.flags.carry;
+                if self.flags.aux_carry || low > 9 {
+                    correction |= 0x06;
                 }
-                if (data_h > 9)  | self.flags.carry {
-                    data_h += 6;
-                    if data_h > 0x0F { carry = true }
+                if self.flags.carry || high > 9 || (high == 9 && low > 9) {
+                    correction |= 0x60;
+                    carry = true;
                 }
-                self.registers.a_reg = (data_h << 4) | (data_l & 0x0F);
+                self.add(correction, false);
                 self.flags.carry = carry;
-                self.flags.zero = self.registers.a_reg == 0;
-                self.flags.sign = self.registers.a_reg & 0x80 != 0;
-                self.flags.parity = parity(self.registers.a_reg);
+            },
+            0xA0 => { // ANA B
+                self.and(self.registers.bc_reg.high);
+            },
+            0xA1 => { // ANA C
+                self.and(self.registers.bc_reg.low);
+            },
+            0xA2 => { // ANA D
+                self.and(self.registers.de_reg.high);
+            },
+            0xA3 => { // ANA E
+                self.and(self.registers.de_reg.low);
+            },
+            0xA4 => { // ANA H
+                self.and(self.registers.hl_reg.high);
+            },
+            0xA5 => { // ANA L
+                self.and(self.registers.hl_reg.low);
+            },
+            0xA6 => { // ANA M
+                let data = self.memory.read_byte(self.registers.hl_reg.get_pair())?;
+                self.and(data);
+            },
+            0xA7 => { // ANA A
+                self.and(self.registers.a_reg);
+            },
+            0xA8 => { // XRA B
+                self.xor(self.registers.bc_reg.high);
+            },
+            0xA9 => { // XRA C
+                self.xor(self.registers.bc_reg.low);
+            },
+            0xAA => { // XRA D
+                self.xor(self.registers.de_reg.high);
+            },
+            0xAB => { // XRA E
+                self.xor(self.registers.de_reg.low);
+            },
+            0xAC => { // XRA H
+                self.xor(self.registers.hl_reg.high);
+            },
+            0xAD => { // XRA L
+                self.xor(self.registers.hl_reg.low);
+            },
+            0xAE => { // XRA M
+                let data = self.memory.read_byte(self.registers.hl_reg.get_pair())?;
+                self.xor(data);
+            },
+            0xAF => { // XRA A
+                self.xor(self.registers.a_reg);
+            },
+            0xB0 => { // ORA B
+                self.or(self.registers.bc_reg.high);
+            },
+            0xB1 => { // ORA C
+                self.or(self.registers.bc_reg.low);
+            },
+            0xB2 => { // ORA D
+                self.or(self.registers.de_reg.high);
+            },
+            0xB3 => { // ORA E
+                self.or(self.registers.de_reg.low);
+            },
+            0xB4 => { // ORA H
+                self.or(self.registers.hl_reg.high);
+            },
+            0xB5 => { // ORA L
+                self.or(self.registers.hl_reg.low);
+            },
+            0xB6 => { // ORA M
+                let data = self.memory.read_byte(self.registers.hl_reg.get_pair())?;
+                self.or(data);
+            },
+            0xB7 => { // ORA A
+                self.or(self.registers.a_reg);
+            },
+            0xB8 => { // CMP B
+                self.compare(self.registers.bc_reg.high);
+            },
+            0xB9 => { // CMP C
+                self.compare(self.registers.bc_reg.low);
+            },
+            0xBA => { // CMP D
+                self.compare(self.registers.de_reg.high);
+            },
+            0xBB => { // CMP E
+                self.compare(self.registers.de_reg.low);
+            },
+            0xBC => { // CMP H
+                self.compare(self.registers.hl_reg.high);
+            },
+            0xBD => { // CMP L
+                self.compare(self.registers.hl_reg.low);
+            },
+            0xBE => { // CMP M
+                let data = self.memory.read_byte(self.registers.hl_reg.get_pair())?;
+                self.compare(data);
+            },
+            0xBF => { // CMP A
+                self.compare(self.registers.a_reg);
             },
             0x2F => { // CMA
                 self.registers.a_reg = !self.registers.a_reg;
@@ -594,242 +930,142 @@ impl CPU {
                 self.flags.carry = !self.flags.carry;
             },
             0xE6 => { // ANI d8
-                self.registers.a_reg &= self.memory.fetch_byte()?;
-                self.flags.carry = false;
-                self.flags.zero = self.registers.a_reg == 0;
-                self.flags.sign = self.registers.a_reg & 0x80 != 0;
-                self.flags.parity = parity(self.registers.a_reg);
-                cycles = 2;
+                let data = self.memory.fetch_byte()?;
+                self.and(data);
             },
             0xEE => { // XRI d8
-                self.registers.a_reg ^= self.memory.fetch_byte()?;
-                self.flags.carry = false;
-                self.flags.zero = self.registers.a_reg == 0;
-                self.flags.sign = self.registers.a_reg & 0x80 != 0;
-                self.flags.parity = parity(self.registers.a_reg);
-                cycles = 2;
+                let data = self.memory.fetch_byte()?;
+                self.xor(data);
             },
             0xF6 => { // ORI d8
-                self.registers.a_reg &= self.memory.fetch_byte()?;
-                self.flags.carry = false;
-                self.flags.zero = self.registers.a_reg == 0;
-                self.flags.sign = self.registers.a_reg & 0x80 != 0;
-                self.flags.parity = parity(self.registers.a_reg);
-                cycles = 2;
+                let data = self.memory.fetch_byte()?;
+                self.or(data);
             },
             0xFE => { // CPI d8
-                let (result, carry) = self.registers.a_reg.overflowing_sub(self.memory.fetch_byte()?);
-                self.flags.carry = carry;
-                self.flags.zero = result == 0;
-                self.flags.sign = result & 0x80 != 0;
-                self.flags.parity = parity(result);
-                cycles = 2;
+                let data = self.memory.fetch_byte()?;
+                self.compare(data);
             },
 
             // ****** Branch Group ******
             // *** Returns ***
             0xC9 => { // RET
                 self.memory.program_counter = self.memory.pop_stack()?;
-                cycles = 3;
             },
             0xC0 => { // RNZ
-                if !self.flags.zero {
-                    self.memory.program_counter = self.memory.pop_stack()?;
-                }
+                cycles += self.return_if(!self.flags.zero)?;
             },
             0xC8 => { // RZ
-                if self.flags.zero {
-                    self.memory.program_counter = self.memory.pop_stack()?;
-                }
+                cycles += self.return_if(self.flags.zero)?;
             },
             0xD0 => { // RNC
-                if !self.flags.carry {
-                    self.memory.program_counter = self.memory.pop_stack()?;
-                }
+                cycles += self.return_if(!self.flags.carry)?;
             },
             0xD8 => { // RC
-                if self.flags.carry {
-                    self.memory.program_counter = self.memory.pop_stack()?;
-                }
+                cycles += self.return_if(self.flags.carry)?;
             },
             0xE0 => { // RPO
-                if !self.flags.parity {
-                    self.memory.program_counter = self.memory.pop_stack()?;
-                }
+                cycles += self.return_if(!self.flags.parity)?;
             },
             0xE8 => { // RPE
-                if self.flags.parity {
-                    self.memory.program_counter = self.memory.pop_stack()?;
-                }
+                cycles += self.return_if(self.flags.parity)?;
             },
             0xF0 => { // RP
-                if !self.flags.sign {
-                    self.memory.program_counter = self.memory.pop_stack()?;
-                }
+                cycles += self.return_if(!self.flags.sign)?;
             },
             0xF8 => { // RM
-                if self.flags.sign {
-                    self.memory.program_counter = self.memory.pop_stack()?;
-                }
+                cycles += self.return_if(self.flags.sign)?;
             },
             
             // *** Jumps ***
             0xC3 => { //JMP a16
                 self.memory.program_counter = self.memory.fetch_two_bytes()?;
-                cycles = 3;
             },
             0xC2 => { // JNZ a16
-                if !self.flags.zero {
-                    self.memory.program_counter = self.memory.fetch_two_bytes()?;
-                }
-                cycles = 3;
+                self.jump_if(!self.flags.zero)?;
             },
             0xCA => { // JZ a16
-                if self.flags.zero {
-                    self.memory.program_counter = self.memory.fetch_two_bytes()?;
-                }
-                cycles = 3;
+                self.jump_if(self.flags.zero)?;
             },
             0xD2 => { // JNC a16
-                if !self.flags.carry {
-                    self.memory.program_counter = self.memory.fetch_two_bytes()?;
-                }
-                cycles = 3;
+                self.jump_if(!self.flags.carry)?;
             },
             0xDA => { // JC a16
-                if self.flags.carry {
-                    self.memory.program_counter = self.memory.fetch_two_bytes()?;
-                }
-                cycles = 3;
+                self.jump_if(self.flags.carry)?;
             },
             0xE2 => { // JPO a16
-                if !self.flags.parity {
-                    self.memory.program_counter = self.memory.fetch_two_bytes()?;
-                }
-                cycles = 3;
+                self.jump_if(!self.flags.parity)?;
             },
             0xEA => { // JPE a16
-                if self.flags.parity {
-                    self.memory.program_counter = self.memory.fetch_two_bytes()?;
-                }
-                cycles = 3;
+                self.jump_if(self.flags.parity)?;
             },
             0xF2 => { // JP a16
-                if !self.flags.sign {
-                    self.memory.program_counter = self.memory.fetch_two_bytes()?;
-                }
-                cycles = 3;
+                self.jump_if(!self.flags.sign)?;
             },
             0xFA => { // JM a16
-                if self.flags.sign {
-                    self.memory.program_counter = self.memory.fetch_two_bytes()?;
-                }
-                cycles = 3;
+                self.jump_if(self.flags.sign)?;
             },
 
             // *** Calls ***
             0xC4 => { // CNZ a16
-                if !self.flags.zero {
-                    self.memory.push_stack(self.memory.program_counter)?;
-                    self.memory.program_counter = self.memory.fetch_two_bytes()?;
-                }
-                cycles = 3;
+                cycles += self.call_if(!self.flags.zero)?;
             },
             0xCC => { // CZ a16
-                if self.flags.zero {
-                    self.memory.push_stack(self.memory.program_counter)?;
-                    self.memory.program_counter = self.memory.fetch_two_bytes()?;
-                }
-                cycles = 3;
+                cycles += self.call_if(self.flags.zero)?;
             },
             0xD4 => { // CNC a16
-                if !self.flags.carry {
-                    self.memory.push_stack(self.memory.program_counter)?;
-                    self.memory.program_counter = self.memory.fetch_two_bytes()?;
-                }
-                cycles = 3;
+                cycles += self.call_if(!self.flags.carry)?;
             },
             0xDC => { // CC a16
-                if self.flags.carry {
-                    self.memory.push_stack(self.memory.program_counter)?;
-                    self.memory.program_counter = self.memory.fetch_two_bytes()?;
-                }
-                cycles = 3;
+                cycles += self.call_if(self.flags.carry)?;
             },
             0xE4 => { // CPO a16
-                if !self.flags.parity {
-                    self.memory.push_stack(self.memory.program_counter)?;
-                    self.memory.program_counter = self.memory.fetch_two_bytes()?;
-                }
-                cycles = 3;
+                cycles += self.call_if(!self.flags.parity)?;
             },
             0xEC => { // CPE a16
-                if self.flags.parity {
-                    self.memory.push_stack(self.memory.program_counter)?;
-                    self.memory.program_counter = self.memory.fetch_two_bytes()?;
-                }
-                cycles = 3;
+                cycles += self.call_if(self.flags.parity)?;
             },
             0xF4 => { // CP a16
-                if !self.flags.sign {
-                    self.memory.push_stack(self.memory.program_counter)?;
-                    self.memory.program_counter = self.memory.fetch_two_bytes()?;
-                }
-                cycles = 3;
+                cycles += self.call_if(!self.flags.sign)?;
             },
             0xFC => { // CM a16
-                if self.flags.sign {
-                    self.memory.push_stack(self.memory.program_counter)?;
-                    self.memory.program_counter = self.memory.fetch_two_bytes()?;
-                }
-                cycles = 3;
+                cycles += self.call_if(self.flags.sign)?;
             },
             0xCD => { // CALL a16
-                self.memory.push_stack(self.memory.program_counter)?; // Push program counter to stack
-                self.memory.program_counter = self.memory.fetch_two_bytes()?; // Set program counter to new address
-                cycles = 5;
-            }
+                self.call_if(true)?;
+            },
 
             // *** Subroutines ***
             0xC7 => { // RST 0
                 self.memory.push_stack(self.memory.program_counter)?; // Push program counter to stack
                 self.memory.program_counter = SR_0_ADDR; // Set program counter to new address
-                cycles = 3;
             },
             0xCF => { // RST 1
                 self.memory.push_stack(self.memory.program_counter)?;
                 self.memory.program_counter = SR_1_ADDR;
-                cycles = 3;
             },
             0xD7 => { // RST 2
                 self.memory.push_stack(self.memory.program_counter)?;
                 self.memory.program_counter = SR_2_ADDR;
-                cycles = 3;
             },
             0xDF => { // RST 3
                 self.memory.push_stack(self.memory.program_counter)?;
                 self.memory.program_counter = SR_3_ADDR;
-                cycles = 3;
             },
             0xE7 => { // RST 4
                 self.memory.push_stack(self.memory.program_counter)?;
                 self.memory.program_counter = SR_4_ADDR;
-                cycles = 3;
             },
             0xEF => { // RST 5
                 self.memory.push_stack(self.memory.program_counter)?;
                 self.memory.program_counter = SR_5_ADDR;
-                cycles = 3;
             },
             0xF7 => { // RST 6
                 self.memory.push_stack(self.memory.program_counter)?;
                 self.memory.program_counter = SR_6_ADDR;
-                cycles = 3;
             },
             0xFF => { // RST 7
                 self.memory.push_stack(self.memory.program_counter)?;
                 self.memory.program_counter = SR_7_ADDR;
-                cycles = 3;
             },
 
             0xE9 => { // PCHL
@@ -839,80 +1075,61 @@ impl CPU {
             // ****** Stack, IO, and Machine Control Group ******
             0x00 => (), // NOP
             0x76 => { // HLT
-                match self.memory.stack_pointer.checked_sub(1) {
-                    Some(x) => self.memory.stack_pointer = x,
-                    None => return Err(CoreError::StackPointerOverflow)
-                }
+                self.halted = true;
             },
             0xC1 => { // POP B
                 self.registers.bc_reg.set_pair(self.memory.pop_stack()?);
-                cycles = 3;
             },
             0xD1 => { // POP D
                 self.registers.de_reg.set_pair(self.memory.pop_stack()?);
-                cycles = 3;
             },
             0xE1 => { // POP H
                 self.registers.hl_reg.set_pair(self.memory.pop_stack()?);
-                cycles = 3;
             },
             0xF1 => { // POP PSW
                 let data = self.memory.pop_stack()?; // Use local to avoid double reference
                 self.restore_psw(data);
-                cycles = 3;
             },
             0xC5 => { // PUSH B
                 self.memory.push_stack(self.registers.bc_reg.get_pair())?;
-                cycles = 3;
             },
             0xD5 => { // PUSH D
                 self.memory.push_stack(self.registers.de_reg.get_pair())?;
-                cycles = 3;
             },
             0xE5 => { // PUSH H
                 self.memory.push_stack(self.registers.hl_reg.get_pair())?;
-                cycles = 3;
             },
             0xF5 => { // PUSH PSW
                 let data = self.generate_psw();
                 self.memory.push_stack(data)?;
-                cycles = 3;
             },
             0xE3 => { // XTHL
                 let temp = self.memory.pop_stack()?;
                 self.memory.push_stack(self.registers.hl_reg.get_pair())?;
                 self.registers.hl_reg.set_pair(temp);
-                cycles = 5;
+            },
+            0xEB => { // XCHG
+                let temp = self.registers.hl_reg.get_pair();
+                self.registers.hl_reg.set_pair(self.registers.de_reg.get_pair());
+                self.registers.de_reg.set_pair(temp);
             },
             0xF9 => { // SPHL
                 self.memory.stack_pointer = self.registers.hl_reg.get_pair();
             },
             0xD3 => { // OUT d8
                 let port = self.memory.fetch_byte()?;
-                let data = self.registers.a_reg;
-                match port {
-                    0x02 => self.shifter.set_offset(data),
-                    0x04 => self.shifter.load(data),
-                    _ => ()
-                }
-                cycles = 3;
+                self.ports.write(port, self.registers.a_reg, self.cycles);
             },
             0xDB => { // IN d8
                 let port = self.memory.fetch_byte()?;
-                let data = match port {
-                    0x01 => self.input.port1,
-                    0x02 => self.input.port2,
-                    0x03 => self.shifter.get_shift(),
-                    _ => 0x00
-                };
-                self.registers.a_reg = data;
-                cycles = 3;
+                self.registers.a_reg = self.ports.read(port);
             },
             0xF3 => { // DI
                 self.interrupt_enable = false;
             },
             0xFB => { // EI
                 self.interrupt_enable = true;
+                self.interrupt_delay = true; // Interrupts are accepted only after the next instruction
             },
 
             _ => {
@@ -925,6 +1142,21 @@ impl CPU {
     }
 }
 
+// Documented instruction that real silicon executes for each undocumented opcode
+fn documented_alias(opcode: u8) -> u8 {
+    match opcode {
+        0x08 | 0x10 | 0x18 | 0x20 | 0x28 | 0x30 | 0x38 => 0x00, // NOP
+        0xCB => 0xC3, // JMP a16
+        0xD9 => 0xC9, // RET
+        0xDD | 0xED | 0xFD => 0xCD, // CALL a16
+        _ => opcode,
+    }
+}
+
+pub fn rst_opcode(vector: u8) -> u8 {
+    0xC7 | ((vector & 0x07) << 3)
+}
+
 fn parity(a: u8) -> bool {
     // Shamelessly inspired by https://graphics.stanford.edu/~seander/bithacks.html#ParityParallel
     let mut a = a;
@@ -936,8 +1168,10 @@ fn parity(a: u8) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
 
-    const BAD_OPS:[u8; 13] = [0x08, 0x10, 0x18, 0x20, 0x28, 0x30, 0x38, 0xCB, 0xD9, 0xDD, 0xE3, 0xED, 0xFD];
+    const BAD_OPS:[u8; 12] = [0x08, 0x10, 0x18, 0x20, 0x28, 0x30, 0x38, 0xCB, 0xD9, 0xDD, 0xED, 0xFD];
     #[test]
     fn all_opcodes_exist() {
         let mut cpu = CPU::new();
@@ -947,4 +1181,608 @@ mod tests {
             }
         }
     }
+    const M_ADDR: u16 = 0x2000;
+
+    // Runs one register/memory ALU opcode with its source operand (B, C, D, E, H, L, M or A) set to `data`
+    fn run_alu(opcode: u8, a: u8, data: u8, carry: bool) -> CPU {
+        let mut cpu = CPU::new();
+        cpu.registers.hl_reg.set_pair(M_ADDR);
+        cpu.registers.a_reg = a;
+        cpu.flags.carry = carry;
+        match opcode & 0x07 {
+            0 => cpu.registers.bc_reg.high = data,
+            1 => cpu.registers.bc_reg.low = data,
+            2 => cpu.registers.de_reg.high = data,
+            3 => cpu.registers.de_reg.low = data,
+            4 => cpu.registers.hl_reg.high = data,
+            5 => cpu.registers.hl_reg.low = data,
+            6 => cpu.memory.write_byte(M_ADDR, data).unwrap(),
+            _ => cpu.registers.a_reg = data,
+        }
+        cpu.execute(opcode).unwrap();
+        cpu
+    }
+
+    // Runs one immediate ALU opcode with `data` as the operand byte
+    fn run_immediate(opcode: u8, a: u8, data: u8, carry: bool) -> CPU {
+        let mut cpu = CPU::new();
+        cpu.memory.write_byte(0x0000, data).unwrap();
+        cpu.registers.a_reg = a;
+        cpu.flags.carry = carry;
+        cpu.execute(opcode).unwrap();
+        assert_eq!(cpu.memory.program_counter, 0x0001);
+        cpu
+    }
+
+    fn assert_flags(cpu: &CPU, zero: bool, sign: bool, parity: bool, carry: bool) {
+        assert_eq!(cpu.flags.zero, zero, "zero");
+        assert_eq!(cpu.flags.sign, sign, "sign");
+        assert_eq!(cpu.flags.parity, parity, "parity");
+        assert_eq!(cpu.flags.carry, carry, "carry");
+    }
+
+    #[test]
+    fn op_add() {
+        for op in 0x80..=0x86 {
+            let cpu = run_alu(op, 0x14, 0x27, true);
+            assert_eq!(cpu.registers.a_reg, 0x3B);
+            assert_flags(&cpu, false, false, false, false);
+            let cpu = run_alu(op, 0xF0, 0x20, false);
+            assert_eq!(cpu.registers.a_reg, 0x10);
+            assert_flags(&cpu, false, false, false, true);
+        }
+        let cpu = run_alu(0x87, 0x80, 0x80, false);
+        assert_eq!(cpu.registers.a_reg, 0x00);
+        assert_flags(&cpu, true, false, true, true);
+    }
+
+    #[test]
+    fn op_adc() {
+        for op in 0x88..=0x8E {
+            let cpu = run_alu(op, 0x14, 0x27, true);
+            assert_eq!(cpu.registers.a_reg, 0x3C);
+            assert_flags(&cpu, false, false, true, false);
+            let cpu = run_alu(op, 0xFF, 0x00, true);
+            assert_eq!(cpu.registers.a_reg, 0x00);
+            assert_flags(&cpu, true, false, true, true);
+        }
+        let cpu = run_alu(0x8F, 0x41, 0x41, true);
+        assert_eq!(cpu.registers.a_reg, 0x83);
+        assert_flags(&cpu, false, true, false, false);
+    }
+
+    #[test]
+    fn op_sub() {
+        for op in 0x90..=0x96 {
+            let cpu = run_alu(op, 0x3E, 0x3E, true);
+            assert_eq!(cpu.registers.a_reg, 0x00);
+            assert_flags(&cpu, true, false, true, false);
+            let cpu = run_alu(op, 0x10, 0x20, false);
+            assert_eq!(cpu.registers.a_reg, 0xF0);
+            assert_flags(&cpu, false, true, true, true);
+        }
+        let cpu = run_alu(0x97, 0x3E, 0x3E, true);
+        assert_eq!(cpu.registers.a_reg, 0x00);
+        assert_flags(&cpu, true, false, true, false);
+    }
+
+    #[test]
+    fn op_sbb() {
+        for op in 0x98..=0x9E {
+            let cpu = run_alu(op, 0x04, 0x02, true);
+            assert_eq!(cpu.registers.a_reg, 0x01);
+            assert_flags(&cpu, false, false, false, false);
+            let cpu = run_alu(op, 0x02, 0x02, true);
+            assert_eq!(cpu.registers.a_reg, 0xFF);
+            assert_flags(&cpu, false, true, true, true);
+        }
+        let cpu = run_alu(0x9F, 0x55, 0x55, true);
+        assert_eq!(cpu.registers.a_reg, 0xFF);
+        assert_flags(&cpu, false, true, true, true);
+    }
+
+    #[test]
+    fn op_ana() {
+        for op in 0xA0..=0xA6 {
+            let cpu = run_alu(op, 0xFC, 0x0F, true);
+            assert_eq!(cpu.registers.a_reg, 0x0C);
+            assert_flags(&cpu, false, false, true, false);
+        }
+        let cpu = run_alu(0xA7, 0x81, 0x81, true);
+        assert_eq!(cpu.registers.a_reg, 0x81);
+        assert_flags(&cpu, false, true, true, false);
+    }
+
+    #[test]
+    fn op_xra() {
+        for op in 0xA8..=0xAE {
+            let cpu = run_alu(op, 0x5C, 0x78, true);
+            assert_eq!(cpu.registers.a_reg, 0x24);
+            assert_flags(&cpu, false, false, true, false);
+        }
+        let cpu = run_alu(0xAF, 0x81, 0x81, true);
+        assert_eq!(cpu.registers.a_reg, 0x00);
+        assert_flags(&cpu, true, false, true, false);
+    }
+
+    #[test]
+    fn op_ora() {
+        for op in 0xB0..=0xB6 {
+            let cpu = run_alu(op, 0x33, 0x0F, true);
+            assert_eq!(cpu.registers.a_reg, 0x3F);
+            assert_flags(&cpu, false, false, true, false);
+        }
+        let cpu = run_alu(0xB7, 0x80, 0x80, true);
+        assert_eq!(cpu.registers.a_reg, 0x80);
+        assert_flags(&cpu, false, true, false, false);
+    }
+
+    #[test]
+    fn op_cmp() {
+        for op in 0xB8..=0xBE {
+            let cpu = run_alu(op, 0x0A, 0x05, false);
+            assert_eq!(cpu.registers.a_reg, 0x0A);
+            assert_flags(&cpu, false, false, true, false);
+            let cpu = run_alu(op, 0x02, 0x05, false);
+            assert_eq!(cpu.registers.a_reg, 0x02);
+            assert_flags(&cpu, false, true, false, true);
+        }
+        let cpu = run_alu(0xBF, 0x42, 0x42, true);
+        assert_eq!(cpu.registers.a_reg, 0x42);
+        assert_flags(&cpu, true, false, true, false);
+    }
+
+    #[test]
+    fn op_immediates() {
+        let cpu = run_immediate(0xC6, 0x14, 0x42, true); // ADI
+        assert_eq!(cpu.registers.a_reg, 0x56);
+        assert_flags(&cpu, false, false, true, false);
+        let cpu = run_immediate(0xCE, 0xFF, 0x00, true); // ACI
+        assert_eq!(cpu.registers.a_reg, 0x00);
+        assert_flags(&cpu, true, false, true, true);
+        let cpu = run_immediate(0xD6, 0x00, 0x01, false); // SUI
+        assert_eq!(cpu.registers.a_reg, 0xFF);
+        assert_flags(&cpu, false, true, true, true);
+        let cpu = run_immediate(0xDE, 0x10, 0x0F, true); // SBI
+        assert_eq!(cpu.registers.a_reg, 0x00);
+        assert_flags(&cpu, true, false, true, false);
+        let cpu = run_immediate(0xE6, 0x3A, 0x0F, true); // ANI
+        assert_eq!(cpu.registers.a_reg, 0x0A);
+        assert_flags(&cpu, false, false, true, false);
+        let cpu = run_immediate(0xEE, 0x3A, 0xFF, true); // XRI
+        assert_eq!(cpu.registers.a_reg, 0xC5);
+        assert_flags(&cpu, false, true, true, false);
+        let cpu = run_immediate(0xF6, 0x3A, 0x0F, true); // ORI
+        assert_eq!(cpu.registers.a_reg, 0x3F);
+        assert_flags(&cpu, false, false, true, false);
+        let cpu = run_immediate(0xFE, 0x4A, 0x40, false); // CPI
+        assert_eq!(cpu.registers.a_reg, 0x4A);
+        assert_flags(&cpu, false, false, true, false);
+    }
+
+    #[test]
+    fn op_xchg() {
+        let mut cpu = CPU::new();
+        cpu.registers.de_reg.set_pair(0x1234);
+        cpu.registers.hl_reg.set_pair(0xABCD);
+        cpu.execute(0xEB).unwrap();
+        assert_eq!(cpu.registers.de_reg.get_pair(), 0xABCD);
+        assert_eq!(cpu.registers.hl_reg.get_pair(), 0x1234);
+    }
+
+    #[test]
+    fn aux_carry() {
+        assert!(run_alu(0x80, 0x0F, 0x01, false).flags.aux_carry); // ADD B
+        assert!(!run_alu(0x80, 0x07, 0x01, false).flags.aux_carry);
+        assert!(run_alu(0x88, 0x0E, 0x01, true).flags.aux_carry); // ADC B
+        assert!(!run_alu(0x90, 0x10, 0x01, false).flags.aux_carry); // SUB B
+        assert!(run_alu(0x90, 0x11, 0x01, false).flags.aux_carry);
+        assert!(run_alu(0xA0, 0x08, 0x00, false).flags.aux_carry); // ANA B
+        assert!(!run_alu(0xA8, 0x08, 0x08, false).flags.aux_carry); // XRA B
+
+        let mut cpu = CPU::new();
+        cpu.registers.bc_reg.high = 0x0F;
+        cpu.execute(0x04).unwrap(); // INR B
+        assert_eq!(cpu.registers.bc_reg.high, 0x10);
+        assert!(cpu.flags.aux_carry);
+        cpu.execute(0x05).unwrap(); // DCR B
+        assert_eq!(cpu.registers.bc_reg.high, 0x0F);
+        assert!(!cpu.flags.aux_carry);
+    }
+
+    #[test]
+    fn op_daa() {
+        // 0x38 + 0x45 = 0x7D, adjusted to BCD 83
+        let mut cpu = run_alu(0x80, 0x38, 0x45, false);
+        cpu.execute(0x27).unwrap();
+        assert_eq!(cpu.registers.a_reg, 0x83);
+        assert_flags(&cpu, false, true, false, false);
+
+        // 0x99 + 0x01 = 0x9A, adjusted to BCD 00 with carry
+        let mut cpu = run_alu(0x80, 0x99, 0x01, false);
+        cpu.execute(0x27).unwrap();
+        assert_eq!(cpu.registers.a_reg, 0x00);
+        assert_flags(&cpu, true, false, true, true);
+
+        // 0x09 + 0x08 = 0x11 with AC set, adjusted to BCD 17
+        let mut cpu = run_alu(0x80, 0x09, 0x08, false);
+        cpu.execute(0x27).unwrap();
+        assert_eq!(cpu.registers.a_reg, 0x17);
+        assert!(!cpu.flags.carry);
+    }
+
+    #[test]
+    fn psw_round_trip() {
+        let mut cpu = CPU::new();
+        cpu.memory.stack_pointer = 0x2400;
+        cpu.registers.a_reg = 0x5A;
+        cpu.flags.set_byte(0xFF);
+        cpu.execute(0xF5).unwrap(); // PUSH PSW
+        assert_eq!(cpu.memory.read_two_bytes(0x23FE).unwrap(), 0x5AD7);
+
+        cpu.memory.write_byte(0x23FE, 0x82).unwrap(); // S and the always-set bit only
+        cpu.execute(0xF1).unwrap(); // POP PSW
+        assert_eq!(cpu.registers.a_reg, 0x5A);
+        assert_flags(&cpu, false, true, false, false);
+        assert!(!cpu.flags.aux_carry);
+        assert_eq!(cpu.flags.get_byte(), 0x82);
+    }
+
+    #[test]
+    fn timing_table_matches_datasheet() {
+        let mut expected = [0u32; 256];
+        for (op, states) in expected.iter_mut().enumerate().take(0xC0).skip(0x40) {
+            let uses_memory = op & 0x07 == 0x06 || (op < 0x80 && op & 0x38 == 0x30);
+            *states = match (op < 0x80, uses_memory) { // MOV and ALU r/M
+                (true, false) => 5,
+                (false, false) => 4,
+                (_, true) => 7,
+            };
+        }
+        let groups: [(u32, &[usize]); 10] = [
+            (4, &[0x00, 0x07, 0x0F, 0x17, 0x1F, 0x27, 0x2F, 0x37, 0x3F, 0xEB, 0xF3, 0xFB,
+                0x08, 0x10, 0x18, 0x20, 0x28, 0x30, 0x38]),
+            (5, &[0x03, 0x13, 0x23, 0x33, 0x0B, 0x1B, 0x2B, 0x3B, 0x04, 0x0C, 0x14, 0x1C, 0x24, 0x2C, 0x3C,
+                0x05, 0x0D, 0x15, 0x1D, 0x25, 0x2D, 0x3D, 0xC0, 0xC8, 0xD0, 0xD8, 0xE0, 0xE8, 0xF0, 0xF8,
+                0xE9, 0xF9]),
+            (7, &[0x02, 0x12, 0x0A, 0x1A, 0x06, 0x0E, 0x16, 0x1E, 0x26, 0x2E, 0x3E,
+                0xC6, 0xCE, 0xD6, 0xDE, 0xE6, 0xEE, 0xF6, 0xFE]),
+            (10, &[0x01, 0x11, 0x21, 0x31, 0x09, 0x19, 0x29, 0x39, 0x34, 0x35, 0x36,
+                0xC1, 0xD1, 0xE1, 0xF1, 0xC3, 0xC2, 0xCA, 0xD2, 0xDA, 0xE2, 0xEA, 0xF2, 0xFA,
+                0xC9, 0xDB, 0xD3, 0xCB, 0xD9]),
+            (11, &[0xC5, 0xD5, 0xE5, 0xF5, 0xC4, 0xCC, 0xD4, 0xDC, 0xE4, 0xEC, 0xF4, 0xFC,
+                0xC7, 0xCF, 0xD7, 0xDF, 0xE7, 0xEF, 0xF7, 0xFF]),
+            (13, &[0x32, 0x3A]),
+            (16, &[0x22, 0x2A]),
+            (17, &[0xCD, 0xDD, 0xED, 0xFD]),
+            (18, &[0xE3]),
+            (7, &[0x76]),
+        ];
+        for (states, ops) in groups {
+            for &op in ops {
+                expected[op] = states;
+            }
+        }
+        for op in 0..256 {
+            assert_eq!(INSTRUCTIONS[op].cycles, expected[op], "opcode {:#04x}", op);
+        }
+    }
+
+    #[test]
+    fn tick_reports_branch_timing() {
+        let mut cpu = CPU::new();
+        cpu.load_rom(&[
+            0x41,             // MOV B,C
+            0xC4, 0x0A, 0x00, // CNZ 0x000A (skipped)
+            0xCC, 0x0A, 0x00, // CZ 0x000A (taken)
+            0x00, 0x00, 0x00,
+            0xC0,             // RNZ (skipped)
+            0xC8,             // RZ (taken)
+        ]).unwrap();
+        cpu.memory.stack_pointer = 0x2400;
+        cpu.flags.zero = true;
+        assert_eq!(cpu.tick().unwrap(), 5);
+        assert_eq!(cpu.tick().unwrap(), 11);
+        assert_eq!(cpu.memory.program_counter, 0x0004);
+        assert_eq!(cpu.tick().unwrap(), 17);
+        assert_eq!(cpu.memory.program_counter, 0x000A);
+        assert_eq!(cpu.tick().unwrap(), 5);
+        assert_eq!(cpu.tick().unwrap(), 11);
+        assert_eq!(cpu.memory.program_counter, 0x0007);
+    }
+
+    #[test]
+    fn halt_until_interrupt() {
+        let mut cpu = CPU::new();
+        cpu.load_rom(&[0x00, 0x76, 0x00]).unwrap(); // NOP, HLT, NOP
+        cpu.memory.stack_pointer = 0x2400;
+        cpu.tick().unwrap();
+        assert_eq!(cpu.tick().unwrap(), 7);
+        assert!(cpu.is_halted());
+        assert_eq!(cpu.memory.stack_pointer, 0x2400);
+        for _ in 0..3 {
+            assert_eq!(cpu.tick().unwrap(), HALTED_CYCLES);
+            assert_eq!(cpu.memory.program_counter, 0x0002);
+        }
+
+        cpu.interrupt_enable = true;
+        cpu.interrupt(rst_opcode(1));
+        assert_eq!(cpu.tick().unwrap(), 11);
+        assert!(!cpu.is_halted());
+        assert_eq!(cpu.memory.program_counter, SR_1_ADDR);
+        assert_eq!(cpu.memory.pop_stack().unwrap(), 0x0002);
+    }
+
+    #[test]
+    fn interrupt_latched_until_enabled() {
+        let mut cpu = CPU::new();
+        cpu.load_rom(&[0x00, 0xFB, 0x00, 0x00]).unwrap(); // NOP, EI, NOP, NOP
+        cpu.memory.stack_pointer = 0x2400;
+        cpu.interrupt(rst_opcode(2));
+        cpu.tick().unwrap(); // NOP, interrupt ignored while disabled
+        assert_eq!(cpu.memory.program_counter, 0x0001);
+        cpu.tick().unwrap(); // EI
+        assert!(cpu.interrupts_enabled());
+        cpu.tick().unwrap(); // NOP still runs before the interrupt is accepted
+        assert_eq!(cpu.memory.program_counter, 0x0003);
+
+        cpu.tick().unwrap();
+        assert_eq!(cpu.memory.program_counter, SR_2_ADDR);
+        assert!(!cpu.interrupts_enabled());
+        assert_eq!(cpu.memory.pop_stack().unwrap(), 0x0003);
+    }
+
+    #[test]
+    fn interrupt_executes_bus_opcode() {
+        let mut cpu = CPU::new();
+        cpu.interrupt_enable = true;
+        cpu.registers.a_reg = 0x41;
+        cpu.interrupt(0x3C); // INR A
+        assert_eq!(cpu.tick().unwrap(), 5);
+        assert_eq!(cpu.registers.a_reg, 0x42);
+        assert_eq!(cpu.memory.program_counter, 0x0000);
+        assert!(!cpu.interrupts_enabled());
+
+        cpu.interrupt_enable = true;
+        cpu.interrupt(0x10); // Undefined opcodes are reported from the bus as well
+        assert!(matches!(cpu.tick(), Err(CoreError::OpcodeError { opcode: 0x10 })));
+    }
+
+    #[test]
+    fn instruction_table_matches_execute() {
+        const START: u16 = 0x1000;
+        for (op, instruction) in INSTRUCTIONS.iter().enumerate().filter(|(_, i)| i.documented) {
+            for flags in [0x02, 0xD7] {
+                let mut cpu = CPU::new();
+                cpu.load_rom_at(&[op as u8, 0x34, 0x12], START).unwrap();
+                cpu.memory.stack_pointer = 0x2400;
+                cpu.memory.write_two_bytes(0x2400, 0x0BAD).unwrap();
+                cpu.registers.a_reg = 0x9B;
+                cpu.registers.bc_reg.set_pair(0x8001);
+                cpu.registers.de_reg.set_pair(0x0FFF);
+                cpu.registers.hl_reg.set_pair(0x20F0);
+                cpu.flags.set_byte(flags);
+
+                let cycles = cpu.tick().unwrap();
+                let next = START + instruction.length as u16;
+                let branched = cpu.memory.program_counter != next;
+                assert!(cycles == instruction.cycles
+                    || (branched && cycles == instruction.cycles + BRANCH_TAKEN_CYCLES),
+                    "{:#04x} {}: {} T-states", op, instruction.mnemonic, cycles);
+                let changed = cpu.flags.get_byte() ^ flags;
+                assert_eq!(changed & !instruction.flags, 0, "{:#04x} {}: flags", op, instruction.mnemonic);
+                // Only jumps, calls, returns, RST and PCHL may leave PC anywhere but the next instruction
+                let is_branch = op >= 0xC0 && (matches!(op & 0x07, 0 | 2 | 4 | 7) || matches!(op, 0xC3 | 0xC9 | 0xCD | 0xE9));
+                assert!(!branched || is_branch,
+                    "{:#04x} {}: PC {:#06x}", op, instruction.mnemonic, cpu.memory.program_counter);
+            }
+        }
+    }
+
+    // Register codes 0-7 in opcode order, with M at 0x2055 through H and L
+    const REGISTER_NAMES: [&str; 8] = ["B", "C", "D", "E", "H", "L", "M", "A"];
+
+    fn read_register(cpu: &mut CPU, code: u8) -> u8 {
+        match code {
+            0 => cpu.registers.bc_reg.high,
+            1 => cpu.registers.bc_reg.low,
+            2 => cpu.registers.de_reg.high,
+            3 => cpu.registers.de_reg.low,
+            4 => cpu.registers.hl_reg.high,
+            5 => cpu.registers.hl_reg.low,
+            6 => cpu.read_memory(cpu.registers.hl_reg.get_pair()).unwrap(),
+            _ => cpu.registers.a_reg,
+        }
+    }
+
+    // The flags and timing check above can't see which register a move wrote, so every MOV, MVI
+    // and LXI is run with a distinct value in each register and its destination checked against
+    // the mnemonic
+    #[test]
+    fn instruction_table_moves_match_execute() {
+        const START: u16 = 0x1000;
+        let seeded = |program: &[u8]| {
+            let mut cpu = CPU::new();
+            cpu.load_rom_at(program, START).unwrap();
+            cpu.registers.bc_reg.set_pair(0x1122);
+            cpu.registers.de_reg.set_pair(0x3344);
+            cpu.registers.hl_reg.set_pair(0x2055);
+            cpu.registers.a_reg = 0x77;
+            cpu.memory.write_byte(0x2055, 0x66).unwrap();
+            cpu
+        };
+
+        for op in (0x40..=0x7F).filter(|&op| op != 0x76) {
+            let (dst, src) = ((op >> 3) & 0x07, op & 0x07);
+            let mnemonic = format!("MOV {},{}", REGISTER_NAMES[dst as usize], REGISTER_NAMES[src as usize]);
+            assert_eq!(INSTRUCTIONS[op as usize].mnemonic, mnemonic, "{:#04x}", op);
+            let mut cpu = seeded(&[op]);
+            let expected = read_register(&mut cpu, src);
+            // Writing H or L moves M, so it is read back from where M was
+            let m_addr = cpu.registers.hl_reg.get_pair();
+            cpu.tick().unwrap();
+            let written = match dst {
+                6 => cpu.read_memory(m_addr).unwrap(),
+                _ => read_register(&mut cpu, dst),
+            };
+            assert_eq!(written, expected, "{:#04x} {}", op, mnemonic);
+        }
+
+        for dst in 0..8 {
+            let op = 0x06 | (dst << 3);
+            let mnemonic = format!("MVI {},d8", REGISTER_NAMES[dst as usize]);
+            assert_eq!(INSTRUCTIONS[op as usize].mnemonic, mnemonic, "{:#04x}", op);
+            let mut cpu = seeded(&[op, 0x99]);
+            let m_addr = cpu.registers.hl_reg.get_pair();
+            cpu.tick().unwrap();
+            let written = match dst {
+                6 => cpu.read_memory(m_addr).unwrap(),
+                _ => read_register(&mut cpu, dst),
+            };
+            assert_eq!(written, 0x99, "{:#04x} {}", op, mnemonic);
+        }
+
+        for (op, pair) in [(0x01, "B"), (0x11, "D"), (0x21, "H"), (0x31, "SP")] {
+            assert_eq!(INSTRUCTIONS[op as usize].mnemonic, format!("LXI {},d16", pair), "{:#04x}", op);
+            let mut cpu = seeded(&[op, 0xCD, 0xAB]);
+            cpu.tick().unwrap();
+            let written = match pair {
+                "B" => cpu.registers.bc_reg.get_pair(),
+                "D" => cpu.registers.de_reg.get_pair(),
+                "H" => cpu.registers.hl_reg.get_pair(),
+                _ => cpu.memory.stack_pointer,
+            };
+            assert_eq!(written, 0xABCD, "{:#04x} LXI {}", op, pair);
+        }
+    }
+
+    #[test]
+    fn instruction_format() {
+        assert_eq!(INSTRUCTIONS[0x41].format(0), "MOV B,C");
+        assert_eq!(INSTRUCTIONS[0x3E].format(0x2A), "MVI A,0x2a");
+        assert_eq!(INSTRUCTIONS[0x21].format(0x2400), "LXI H,0x2400");
+        assert_eq!(INSTRUCTIONS[0xCD].format(0x01E6), "CALL 0x01e6");
+    }
+
+    #[test]
+    fn undocumented_opcodes_strict() {
+        let mut cpu = CPU::new();
+        for op in BAD_OPS {
+            assert!(!INSTRUCTIONS[op as usize].documented, "{:#04x}", op);
+            assert!(matches!(cpu.execute(op), Err(CoreError::OpcodeError { opcode }) if opcode == op));
+        }
+    }
+
+    #[test]
+    fn undocumented_opcodes_execute() {
+        for (op, instruction) in INSTRUCTIONS.iter().enumerate().filter(|(_, i)| !i.documented) {
+            let mut cpu = CPU::new();
+            cpu.set_undocumented_opcodes(true);
+            cpu.load_rom_at(&[op as u8, 0x34, 0x12], 0x1000).unwrap();
+            cpu.memory.stack_pointer = 0x2400;
+            cpu.memory.write_two_bytes(0x2400, 0x0BAD).unwrap();
+            assert_eq!(cpu.tick().unwrap(), instruction.cycles);
+            let (pc, sp) = match instruction.mnemonic {
+                "NOP" => (0x1001, 0x2400),
+                "JMP a16" => (0x1234, 0x2400),
+                "RET" => (0x0BAD, 0x2402),
+                "CALL a16" => (0x1234, 0x23FE),
+                _ => panic!("unexpected alias {}", instruction.mnemonic),
+            };
+            assert_eq!(cpu.memory.program_counter, pc, "{:#04x}", op);
+            assert_eq!(cpu.memory.stack_pointer, sp, "{:#04x}", op);
+        }
+    }
+
+    #[test]
+    fn program_counter_wraps() {
+        let mut cpu = CPU::new();
+        cpu.set_wrap_reporting(true);
+        cpu.load_rom_at(&[0x00], 0xFFFF).unwrap();
+        cpu.tick().unwrap();
+        assert_eq!(cpu.memory.program_counter, 0x0000);
+        assert_eq!(cpu.take_address_wraps(), vec![AddressWrap::ProgramCounter]);
+    }
+
+    #[test]
+    fn in_out_use_port_devices() {
+        let mut cpu = CPU::new();
+        let shifter = Rc::new(RefCell::new(ShiftRegister::new()));
+        let inputs = Rc::new(RefCell::new(Inputs::new()));
+        cpu.ports_mut().attach(shifter, &[SHIFT_RESULT_PORT], &[SHIFT_OFFSET_PORT, SHIFT_DATA_PORT]);
+        cpu.ports_mut().attach(inputs.clone(), &INPUT_PORTS, &[]);
+        cpu.load_rom(&[
+            0x3E, 0xAB, // MVI A,0xAB
+            0xD3, 0x04, // OUT 4
+            0x3E, 0x04, // MVI A,0x04
+            0xD3, 0x02, // OUT 2
+            0xDB, 0x03, // IN 3
+            0xDB, 0x01, // IN 1
+            0xDB, 0x07, // IN 7
+        ]).unwrap();
+        for _ in 0..5 {
+            cpu.tick().unwrap();
+        }
+        assert_eq!(cpu.registers.a_reg, 0xB0);
+        inputs.borrow_mut().player1_fire(ButtonState::Pressed);
+        cpu.tick().unwrap();
+        assert_eq!(cpu.registers.a_reg, 0x19);
+        cpu.tick().unwrap();
+        assert_eq!(cpu.registers.a_reg, 0x00);
+    }
+
+    #[test]
+    fn watchdog_reset() {
+        let mut cpu = CPU::new();
+        let watchdog = Rc::new(RefCell::new(Watchdog::with_timeout(20)));
+        cpu.ports_mut().attach(watchdog.clone(), &[], &[WATCHDOG_PORT]);
+        cpu.load_rom(&[
+            0xFB,             // EI
+            0xD3, 0x06,       // OUT 6
+            0x76,             // HLT
+        ]).unwrap();
+        // EI and OUT take 14 cycles and kick it, then HLT (7) and one idle tick (4) starve it
+        for tick in 1..=4 {
+            let cycles = cpu.tick().unwrap();
+            let expired = watchdog.borrow_mut().elapse(cycles);
+            assert_eq!(expired, tick == 4);
+            if expired {
+                cpu.reset();
+            }
+        }
+        assert_eq!(watchdog.borrow().resets(), 1);
+        assert_eq!(cpu.memory.program_counter, 0x0000);
+        assert!(!cpu.is_halted());
+        assert!(!cpu.interrupts_enabled());
+    }
+
+    #[test]
+    fn sound_events_timestamped_with_cycles() {
+        let mut cpu = CPU::new();
+        let sound = Rc::new(RefCell::new(SoundLatches::new()));
+        cpu.ports_mut().attach(sound.clone(), &[], &[SOUND_PORT_1, SOUND_PORT_2]);
+        cpu.load_rom(&[
+            0x3E, 0x02, // MVI A,0x02
+            0xD3, 0x03, // OUT 3
+            0xD3, 0x05, // OUT 5
+        ]).unwrap();
+        for _ in 0..3 {
+            cpu.tick().unwrap();
+        }
+        assert_eq!(cpu.cycles(), 27);
+        assert_eq!(sound.borrow_mut().take_events(), vec![
+            SoundEvent::Start { sound: Sound::Shot, cycle: 7 },
+            SoundEvent::Start { sound: Sound::Fleet2, cycle: 17 },
+        ]);
+    }
+
+    #[test]
+    fn rom_reads_dip_switches() {
+        let mut cpu = CPU::new();
+        let dips = "ships=5,bonus=1500,coin-info=off".parse().unwrap();
+        cpu.ports_mut().attach(Rc::new(RefCell::new(Inputs::with_dip_switches(dips))), &INPUT_PORTS, &[]);
+        cpu.load_rom(&[0xDB, 0x02]).unwrap(); // IN 2
+        cpu.tick().unwrap();
+        assert_eq!(cpu.registers.a_reg, 0x82);
+    }
 }
\ No newline at end of file
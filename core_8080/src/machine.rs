@@ -0,0 +1,256 @@
+use std::cell::{RefCell, RefMut};
+use std::rc::Rc;
+use crate::{CPU, rst_opcode};
+use crate::core_error::CoreError;
+use crate::framebuffer::{Framebuffer, VRAM_SIZE, VRAM_START};
+use crate::io::Inputs;
+use crate::machine_config::{MachineConfig, MemoryLayout, SoundBoard};
+use crate::shift_register::ShiftRegister;
+use crate::sound::{SoundEvent, SoundLatches};
+use crate::timing::{line_cycle, CYCLES_PER_FRAME, MID_SCREEN_LINE, VBLANK_LINE};
+use crate::watchdog::Watchdog;
+
+const MID_SCREEN_VECTOR: u8 = 1;
+const VBLANK_VECTOR: u8 = 2;
+
+// Something that happened during a frame, stamped with the CPU cycle count
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameEvent {
+    Interrupt { vector: u8, cycle: u64 },
+    WatchdogReset { cycle: u64 },
+    Sound(SoundEvent),
+}
+
+impl FrameEvent {
+    pub fn cycle(&self) -> u64 {
+        match *self {
+            FrameEvent::Interrupt { cycle, .. } | FrameEvent::WatchdogReset { cycle } => cycle,
+            FrameEvent::Sound(SoundEvent::Start { cycle, .. } | SoundEvent::Stop { cycle, .. }) => cycle,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameSummary {
+    pub cycles: u32,
+    pub events: Vec<FrameEvent>, // In cycle order
+}
+
+// A complete board: the CPU wired to the devices its MachineConfig lists, run a video frame at
+// a time
+pub struct Machine {
+    cpu: CPU,
+    config: MachineConfig,
+    inputs: Rc<RefCell<Inputs>>,
+    watchdog: Option<Rc<RefCell<Watchdog>>>,
+    sound: Option<Rc<RefCell<SoundLatches>>>,
+    frame_cycle: u32, // Position in the current frame, carried over when an instruction overruns
+    frames: u64,
+}
+
+impl Machine {
+    pub fn new(config: MachineConfig, rom: &[u8]) -> Result<Self, CoreError> {
+        let mut cpu = CPU::with_bus(Box::new(config.memory.memory_map()));
+        load_banks(&mut cpu, &config.memory, rom)?;
+
+        let inputs = Rc::new(RefCell::new(Inputs::with_layout(config.inputs.clone(), config.dip_switches)));
+        cpu.ports_mut().attach(inputs.clone(), &config.inputs.read_ports(), &[]);
+        let shifter_ports = config.port_map.shifter;
+        let shifter = Rc::new(RefCell::new(ShiftRegister::with_ports(shifter_ports)));
+        cpu.ports_mut().attach(shifter, &shifter_ports.read_ports(), &shifter_ports.write_ports());
+
+        // Boards without a watchdog never reset on their own
+        let watchdog = config.port_map.watchdog.map(|port| {
+            let watchdog = Rc::new(RefCell::new(Watchdog::new()));
+            cpu.ports_mut().attach(watchdog.clone(), &[], &[port]);
+            watchdog
+        });
+        let sound = (config.sound_board == SoundBoard::Invaders).then(|| {
+            let sound = Rc::new(RefCell::new(SoundLatches::new()));
+            cpu.ports_mut().attach(sound.clone(), &[], &config.port_map.sound);
+            sound
+        });
+
+        Ok(Self {
+            cpu,
+            config,
+            inputs,
+            watchdog,
+            sound,
+            frame_cycle: 0,
+            frames: 0,
+        })
+    }
+
+    pub fn config(&self) -> &MachineConfig {
+        &self.config
+    }
+
+    pub fn cpu(&self) -> &CPU {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut CPU {
+        &mut self.cpu
+    }
+
+    pub fn inputs(&self) -> RefMut<'_, Inputs> {
+        self.inputs.borrow_mut()
+    }
+
+    // The screen as it stands, flipped when a cocktail cabinet is showing player 2's turn
+    pub fn framebuffer(&mut self) -> Result<Framebuffer, CoreError> {
+        let vram = (0..VRAM_SIZE as u16)
+            .map(|offset| self.cpu.read_memory(VRAM_START + offset))
+            .collect::<Result<Vec<u8>, CoreError>>()?;
+        let screen = Framebuffer::decode(&vram);
+        match &self.sound {
+            Some(sound) if sound.borrow().screen_flipped() => Ok(screen.flipped()),
+            _ => Ok(screen),
+        }
+    }
+
+    // Frames run since power on
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    // Runs the CPU for one 60 Hz frame, raising RST 1 as the beam passes mid-screen and RST 2
+    // as it enters VBlank
+    pub fn run_frame(&mut self) -> Result<FrameSummary, CoreError> {
+        let start = self.cpu.cycles();
+        let mut events = Vec::new();
+        let mut interrupts = [
+            (line_cycle(MID_SCREEN_LINE), MID_SCREEN_VECTOR, false),
+            (line_cycle(VBLANK_LINE), VBLANK_VECTOR, false),
+        ];
+
+        while self.frame_cycle < CYCLES_PER_FRAME {
+            for (line_cycle, vector, raised) in interrupts.iter_mut() {
+                if !*raised && self.frame_cycle >= *line_cycle {
+                    self.cpu.interrupt(rst_opcode(*vector));
+                    events.push(FrameEvent::Interrupt { vector: *vector, cycle: self.cpu.cycles() });
+                    *raised = true;
+                }
+            }
+
+            let cycles = self.cpu.tick()?;
+            self.frame_cycle += cycles;
+            self.inputs.borrow_mut().elapse(cycles);
+            if self.watchdog.as_ref().is_some_and(|watchdog| watchdog.borrow_mut().elapse(cycles)) {
+                self.cpu.reset();
+                events.push(FrameEvent::WatchdogReset { cycle: self.cpu.cycles() });
+            }
+        }
+        self.frame_cycle -= CYCLES_PER_FRAME;
+        self.frames += 1;
+
+        if let Some(sound) = &self.sound {
+            events.extend(sound.borrow_mut().take_events().into_iter().map(FrameEvent::Sound));
+        }
+        events.sort_by_key(FrameEvent::cycle);
+        Ok(FrameSummary {
+            cycles: (self.cpu.cycles() - start) as u32,
+            events,
+        })
+    }
+}
+
+// Copies each ROM bank of the image to where the board decodes it, so images for the extended
+// boards can span the RAM between their banks
+fn load_banks(cpu: &mut CPU, memory: &MemoryLayout, rom: &[u8]) -> Result<(), CoreError> {
+    if rom.len() > memory.image_size() {
+        return Err(CoreError::RomSizeError)
+    }
+    for &(start, end) in &memory.rom {
+        let bank = rom.get(start as usize..rom.len().min(end as usize + 1)).unwrap_or_default();
+        if !bank.is_empty() {
+            cpu.load_rom_at(bank, start)?;
+        }
+    }
+    cpu.reset();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sound::Sound;
+    use crate::framebuffer::SCREEN_HEIGHT;
+
+    // Counts RST 1 in B and RST 2 in C, and plays the shot sound from the VBlank handler
+    fn counting_rom() -> Vec<u8> {
+        let mut rom = vec![0x00; 0x20];
+        rom[0x00..0x07].copy_from_slice(&[
+            0x31, 0x00, 0x24, // LXI SP,0x2400
+            0xFB,             // EI
+            0xC3, 0x04, 0x00, // JMP 0x0004
+        ]);
+        rom[0x08..0x0B].copy_from_slice(&[
+            0x04, // INR B
+            0xFB, // EI
+            0xC9, // RET
+        ]);
+        rom[0x10..0x17].copy_from_slice(&[
+            0x0C,       // INR C
+            0x3E, 0x02, // MVI A,0x02
+            0xD3, 0x03, // OUT 3
+            0xFB,       // EI
+            0xC9,       // RET
+        ]);
+        rom
+    }
+
+    #[test]
+    fn frame_raises_both_interrupts() {
+        let mut machine = Machine::new(MachineConfig::space_invaders(), &counting_rom()).unwrap();
+        let summary = machine.run_frame().unwrap();
+        assert!(summary.cycles >= CYCLES_PER_FRAME);
+        assert_eq!(machine.cpu.registers.bc_reg.high, 1);
+        assert_eq!(machine.cpu.registers.bc_reg.low, 1);
+
+        let interrupts: Vec<(u8, u64)> = summary.events.iter()
+            .filter_map(|event| match *event {
+                FrameEvent::Interrupt { vector, cycle } => Some((vector, cycle)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(interrupts.len(), 2);
+        assert_eq!(interrupts[0].0, MID_SCREEN_VECTOR);
+        assert_eq!(interrupts[1].0, VBLANK_VECTOR);
+        // Raised at the first instruction boundary past each line, at most one JMP late
+        for ((_, cycle), line) in interrupts.iter().zip([MID_SCREEN_LINE, VBLANK_LINE]) {
+            assert!((line_cycle(line) as u64..line_cycle(line) as u64 + 10).contains(cycle));
+        }
+        assert!(summary.events.iter().any(|e| matches!(e, FrameEvent::Sound(SoundEvent::Start { sound: Sound::Shot, .. }))));
+        assert!(summary.events.windows(2).all(|pair| pair[0].cycle() <= pair[1].cycle()));
+    }
+
+    #[test]
+    fn frames_keep_average_rate() {
+        let mut machine = Machine::new(MachineConfig::space_invaders(), &counting_rom()).unwrap();
+        let mut cycles = 0;
+        for _ in 0..60 {
+            cycles += machine.run_frame().unwrap().cycles as u64;
+        }
+        assert_eq!(machine.frames(), 60);
+        assert_eq!(machine.cpu.registers.bc_reg.high, 60);
+        // Overruns are carried into the next frame, so a second of frames stays within one
+        // instruction of the clock rate
+        assert!(cycles.abs_diff(60 * CYCLES_PER_FRAME as u64) <= 18);
+    }
+
+    #[test]
+    fn framebuffer_reads_vram() {
+        let rom = [
+            0x3E, 0x01,       // MVI A,0x01
+            0x32, 0x00, 0x24, // STA 0x2400
+            0x76,             // HLT
+        ];
+        let mut machine = Machine::new(MachineConfig::space_invaders(), &rom).unwrap();
+        machine.run_frame().unwrap();
+        let screen = machine.framebuffer().unwrap();
+        assert!(screen.is_lit(0, SCREEN_HEIGHT - 1));
+        assert!(!screen.is_lit(0, SCREEN_HEIGHT - 2));
+    }
+}
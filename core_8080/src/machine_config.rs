@@ -0,0 +1,320 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::bus::MemoryMap;
+use crate::core_error::CoreError;
+use crate::dip_switches::DipSwitches;
+use crate::io::InputLayout;
+use crate::shift_register::{ShifterPorts, SHIFT_OFFSET_PORT, SHIFT_RESULT_PORT, SHIFT_DATA_PORT};
+use crate::watchdog::WATCHDOG_PORT;
+use crate::sound::{SOUND_PORT_1, SOUND_PORT_2};
+
+// Midway 8080 black and white titles that run on this core
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Game {
+    SpaceInvaders,
+    SpaceInvadersPart2,
+    LunarRescue,
+    GunFight,
+    SeaWolf,
+    BootHill,
+}
+
+const GAME_NAMES: [(Game, &str); 6] = [
+    (Game::SpaceInvaders, "invaders"),
+    (Game::SpaceInvadersPart2, "invadpt2"),
+    (Game::LunarRescue, "lrescue"),
+    (Game::GunFight, "gunfight"),
+    (Game::SeaWolf, "seawolf"),
+    (Game::BootHill, "boothill"),
+];
+
+impl fmt::Display for Game {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (_, name) = GAME_NAMES.iter().find(|(game, _)| game == self).unwrap();
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Game {
+    type Err = CoreError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        GAME_NAMES.iter()
+            .find(|(_, name)| name.eq_ignore_ascii_case(s.trim()))
+            .map(|&(game, _)| game)
+            .ok_or_else(|| CoreError::UnknownGameError { name: s.to_string() })
+    }
+}
+
+// Which I/O port each device of the board answers on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortMap {
+    pub shifter: ShifterPorts,
+    pub watchdog: Option<u8>,
+    pub sound: Vec<u8>,
+}
+
+impl PortMap {
+    pub fn space_invaders() -> Self {
+        Self {
+            shifter: ShifterPorts::space_invaders(),
+            watchdog: Some(WATCHDOG_PORT),
+            sound: vec![SOUND_PORT_1, SOUND_PORT_2],
+        }
+    }
+}
+
+// Where a board decodes program ROM, as inclusive address ranges. RAM, VRAM and the mirrors
+// are the same on every Midway 8080 board.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryLayout {
+    pub rom: Vec<(u16, u16)>,
+}
+
+impl MemoryLayout {
+    // The map MemoryMap::space_invaders describes
+    pub fn space_invaders() -> Self {
+        Self {
+            rom: vec![(0x0000, 0x1FFF)],
+        }
+    }
+
+    // Later boards add a second bank of ROM at 4000-5FFF
+    pub fn extended() -> Self {
+        Self {
+            rom: vec![(0x0000, 0x1FFF), (0x4000, 0x5FFF)],
+        }
+    }
+
+    pub fn memory_map(&self) -> MemoryMap {
+        MemoryMap::midway(&self.rom)
+    }
+
+    // Bytes from address 0 to the end of the highest bank, the largest image the board holds
+    pub fn image_size(&self) -> usize {
+        self.rom.iter().map(|&(_, end)| end as usize + 1).max().unwrap_or(0)
+    }
+
+    // Lays out ROM data that skips the gaps between banks, as dumps joined end to end do, into an
+    // image for Machine::new. Data already the size of the full image is taken as laid out.
+    pub fn image_from(&self, data: &[u8]) -> Result<Vec<u8>, CoreError> {
+        if data.len() == self.image_size() {
+            return Ok(data.to_vec())
+        }
+        let mut image = Vec::new();
+        let mut rest = data;
+        for &(start, end) in &self.rom {
+            if rest.is_empty() {
+                break
+            }
+            let take = rest.len().min((end - start) as usize + 1);
+            image.resize(start as usize, 0x00);
+            image.extend_from_slice(&rest[..take]);
+            rest = &rest[take..];
+        }
+        match rest.is_empty() {
+            true => Ok(image),
+            false => Err(CoreError::RomSizeError),
+        }
+    }
+}
+
+// Only the Space Invaders sound board is decoded into SoundEvents. The other games have
+// discrete analog boards, whose ports are still listed in the PortMap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundBoard {
+    Invaders,
+    Unemulated,
+}
+
+// Coloured cellophane stuck over the monochrome monitor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overlay {
+    None,
+    Midway,
+    Taito,
+}
+
+// Everything that differs between boards running the same core
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MachineConfig {
+    pub game: Game,
+    pub memory: MemoryLayout,
+    pub port_map: PortMap,
+    pub inputs: InputLayout,
+    pub sound_board: SoundBoard,
+    pub dip_switches: DipSwitches,
+    pub overlay: Overlay,
+}
+
+impl MachineConfig {
+    pub fn space_invaders() -> Self {
+        Self::for_game(Game::SpaceInvaders)
+    }
+
+    pub fn for_game(game: Game) -> Self {
+        let invaders = Self {
+            game,
+            memory: MemoryLayout::space_invaders(),
+            port_map: PortMap::space_invaders(),
+            inputs: InputLayout::space_invaders(),
+            sound_board: SoundBoard::Invaders,
+            dip_switches: DipSwitches::default(),
+            overlay: Overlay::Midway,
+        };
+        match game {
+            Game::SpaceInvaders => invaders,
+            // Taito's follow-ups kept the Space Invaders I/O but need the second ROM bank
+            Game::SpaceInvadersPart2 | Game::LunarRescue => Self {
+                memory: MemoryLayout::extended(),
+                ..invaders.overlay(Overlay::Taito)
+            },
+            Game::GunFight => Self {
+                port_map: PortMap {
+                    // A plain MB14241, as in MAME's gunfight I/O map
+                    shifter: ShifterPorts { offset: SHIFT_OFFSET_PORT, data: SHIFT_DATA_PORT, result: SHIFT_RESULT_PORT, reversible: false, reversed_result: None },
+                    watchdog: None,
+                    sound: vec![0x01],
+                },
+                inputs: InputLayout::gun_fight(),
+                sound_board: SoundBoard::Unemulated,
+                ..invaders.overlay(Overlay::None)
+            },
+            Game::SeaWolf => Self {
+                port_map: PortMap {
+                    shifter: ShifterPorts { offset: 0x04, data: 0x03, result: 0x03, reversible: false, reversed_result: Some(0x00) },
+                    watchdog: None,
+                    sound: vec![0x05],
+                },
+                inputs: InputLayout::sea_wolf(),
+                sound_board: SoundBoard::Unemulated,
+                ..invaders.overlay(Overlay::None)
+            },
+            Game::BootHill => Self {
+                port_map: PortMap {
+                    shifter: ShifterPorts { offset: 0x01, data: 0x02, result: 0x03, reversible: true, reversed_result: None },
+                    watchdog: Some(0x04),
+                    sound: vec![0x03, 0x05, 0x06],
+                },
+                inputs: InputLayout::gun_fight(),
+                sound_board: SoundBoard::Unemulated,
+                ..invaders.overlay(Overlay::None)
+            },
+        }
+    }
+
+    pub fn overlay(mut self, overlay: Overlay) -> Self {
+        self.overlay = overlay;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::{ButtonState, Control};
+    use crate::machine::Machine;
+
+    // Copies input `port` to 0x2000 in a loop. On the extended boards the loop lives in the second
+    // ROM bank, so it only runs if that bank is decoded.
+    fn input_echo_rom(port: u8, banked: bool) -> Vec<u8> {
+        let echo = [
+            0xDB, port,       // IN port
+            0x32, 0x00, 0x20, // STA 0x2000
+        ];
+        if !banked {
+            return [&echo[..], &[0xC3, 0x00, 0x00]].concat() // JMP 0x0000
+        }
+        let mut rom = vec![0x00; 0x4000];
+        rom[0x0000..0x0003].copy_from_slice(&[0xC3, 0x00, 0x40]); // JMP 0x4000
+        rom.extend_from_slice(&echo);
+        rom.extend_from_slice(&[0xC3, 0x00, 0x40]);
+        rom
+    }
+
+    #[test]
+    fn game_names_round_trip() {
+        for (game, name) in GAME_NAMES {
+            assert_eq!(name.parse::<Game>().unwrap(), game);
+            assert_eq!(game.to_string(), name);
+        }
+        assert!("galaxian".parse::<Game>().is_err());
+    }
+
+    #[test]
+    fn ports_do_not_collide() {
+        for (game, _) in GAME_NAMES {
+            let config = MachineConfig::for_game(game);
+            let mut reads = config.inputs.read_ports();
+            reads.extend(config.port_map.shifter.read_ports());
+            let mut writes = config.port_map.shifter.write_ports();
+            writes.extend(config.port_map.watchdog);
+            writes.extend(&config.port_map.sound);
+            for ports in [&mut reads, &mut writes] {
+                let count = ports.len();
+                ports.sort();
+                ports.dedup();
+                assert_eq!(ports.len(), count, "{}", game);
+            }
+        }
+    }
+
+    #[test]
+    fn every_game_runs_on_the_core() {
+        // A control each game reads, with the port and bit it is wired to
+        let cases = [
+            (Game::SpaceInvaders, Control::P1Fire, 0x01, 0x10, false),
+            (Game::SpaceInvadersPart2, Control::P2Left, 0x02, 0x20, false),
+            (Game::LunarRescue, Control::P1Start, 0x01, 0x04, false),
+            (Game::GunFight, Control::P1Up, 0x00, 0x01, true),
+            (Game::SeaWolf, Control::P1Fire, 0x01, 0x20, false),
+            (Game::BootHill, Control::P2Down, 0x01, 0x02, true),
+        ];
+        for (game, control, port, mask, active_low) in cases {
+            let config = MachineConfig::for_game(game);
+            let banked = config.memory.rom.len() > 1;
+            let mut machine = Machine::new(config, &input_echo_rom(port, banked)).unwrap();
+            machine.run_frame().unwrap();
+            let idle = machine.cpu_mut().read_memory(0x2000).unwrap();
+            machine.inputs().set(control, ButtonState::Pressed);
+            machine.run_frame().unwrap();
+            let pressed = machine.cpu_mut().read_memory(0x2000).unwrap();
+
+            assert_eq!(idle ^ pressed, mask, "{}", game);
+            assert_eq!(pressed & mask == 0, active_low, "{}", game);
+            // RAM shows through its mirror at 6000 and again above 8000
+            assert_eq!(machine.cpu_mut().read_memory(0x6000).unwrap(), pressed, "{}", game);
+            assert_eq!(machine.cpu_mut().read_memory(0xA000).unwrap(), pressed, "{}", game);
+        }
+    }
+
+    #[test]
+    fn joined_dumps_fill_the_banks_in_order() {
+        let layout = MemoryLayout::extended();
+        let image = layout.image_from(&[0x11; 0x2800]).unwrap();
+        assert_eq!(image.len(), 0x4800);
+        assert_eq!(image[0x1FFF], 0x11);
+        assert_eq!(image[0x2000], 0x00);
+        assert_eq!(image[0x47FF], 0x11);
+
+        assert_eq!(layout.image_from(&[0x22; 0x6000]).unwrap(), vec![0x22; 0x6000]);
+        assert_eq!(layout.image_from(&[0x33; 0x0800]).unwrap(), vec![0x33; 0x0800]);
+        assert!(matches!(layout.image_from(&[0x00; 0x4001]), Err(CoreError::RomSizeError)));
+        assert!(matches!(MemoryLayout::space_invaders().image_from(&[0x00; 0x2001]), Err(CoreError::RomSizeError)));
+    }
+
+    #[test]
+    fn second_rom_bank_only_on_extended_boards() {
+        let mut rom = input_echo_rom(0x01, true);
+        rom[0x2000] = 0x99;
+        assert!(matches!(Machine::new(MachineConfig::space_invaders(), &rom), Err(CoreError::RomSizeError)));
+
+        let mut machine = Machine::new(MachineConfig::for_game(Game::LunarRescue), &rom).unwrap();
+        assert_eq!(machine.cpu_mut().read_memory(0x4000).unwrap(), 0xDB);
+        assert_eq!(machine.cpu_mut().read_memory(0xC000).unwrap(), 0xDB);
+        assert_eq!(machine.cpu_mut().read_memory(0x2000).unwrap(), 0x00); // The gap in the image isn't loaded
+
+        let mut machine = Machine::new(MachineConfig::space_invaders(), &input_echo_rom(0x01, false)).unwrap();
+        assert_eq!(machine.cpu_mut().read_memory(0x4000).unwrap(), 0xFF); // Open bus
+    }
+}
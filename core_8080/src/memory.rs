@@ -1,97 +1,197 @@
+use crate::bus::Bus;
 use crate::core_error::CoreError;
+use std::collections::VecDeque;
 
-const MEM_SIZE: usize = 0x10000;
-const ROM_SIZE: usize = 0x2000;
+// Wraps kept for take_wraps. Once full the oldest are dropped, so reporting can be left on.
+const MAX_WRAPS: usize = 256;
+
+// An access that crossed the top of the address space and wrapped around to 0x0000
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressWrap {
+    ProgramCounter,
+    StackPointer,
+    Word { address: u16 }, // Two byte read or write starting at 0xFFFF
+}
 
 pub struct Memory {
-    pub ram: [u8; MEM_SIZE],
+    bus: Box<dyn Bus>,
     pub program_counter: u16,
     pub stack_pointer: u16,
+    report_wraps: bool,
+    wraps: VecDeque<AddressWrap>,
 }
 
 impl Memory {
-    pub fn new() -> Self {
+    pub fn with_bus(bus: Box<dyn Bus>) -> Self {
         Self {
-            ram: [0; MEM_SIZE],
+            bus,
             program_counter: 0,
             stack_pointer: 0,
+            report_wraps: false,
+            wraps: VecDeque::new(),
         }
     }
 
     pub fn load_rom(&mut self, buffer: &[u8], start_addr: u16) -> Result<(), CoreError> {
-        if buffer.len() > ROM_SIZE {
-            return Err(CoreError::RomSizeError)
-        }
-        let start = start_addr as usize;
-        let end = (start_addr as usize) + buffer.len();
-        self.ram[start..end].copy_from_slice(buffer);
+        self.bus.load(start_addr, buffer)?;
         self.program_counter = start_addr;
         Ok(())
     }
 
-    pub fn fetch_byte(&mut self) -> Result<u8, CoreError> {
-        if (self.program_counter as usize) < self.ram.len() {
-            let byte = self.ram[self.program_counter as usize];
-            match self.program_counter.checked_add(1) {
-                Some(x) => self.program_counter = x,
-                None => return Err(CoreError::ProgramCounterOverflow)
+    // Addresses always wrap modulo 64K like the real address bus. With reporting enabled each
+    // wrap is also recorded so diagnostics can spot code running off the end of memory.
+    pub fn set_wrap_reporting(&mut self, enabled: bool) {
+        self.report_wraps = enabled;
+    }
+
+    pub fn take_wraps(&mut self) -> Vec<AddressWrap> {
+        self.wraps.drain(..).collect()
+    }
+
+    fn record_wrap(&mut self, wrap: AddressWrap) {
+        if self.report_wraps {
+            if self.wraps.len() == MAX_WRAPS {
+                self.wraps.pop_front();
             }
-            Ok(byte)
-        } else {
-            Err(CoreError::IndexError { index: self.program_counter })
+            self.wraps.push_back(wrap);
         }
     }
 
+    pub fn fetch_byte(&mut self) -> Result<u8, CoreError> {
+        let byte = self.read_byte(self.program_counter)?;
+        let (next, wrapped) = self.program_counter.overflowing_add(1);
+        if wrapped {
+            self.record_wrap(AddressWrap::ProgramCounter);
+        }
+        self.program_counter = next;
+        Ok(byte)
+    }
+
     pub fn fetch_two_bytes(&mut self) -> Result<u16, CoreError> {
         let data_low = self.fetch_byte()?;
         let data_high = self.fetch_byte()?;
         Ok((data_high as u16) << 8 | data_low as u16)
     }
 
-    pub fn read_byte(&self, address: u16) -> Result<u8, CoreError> {
-        if (address as usize) < self.ram.len() {
-            Ok(self.ram[address as usize])
-        } else {
-            Err(CoreError::IndexError { index: address })
-        }
+    pub fn read_byte(&mut self, address: u16) -> Result<u8, CoreError> {
+        self.bus.read_byte(address)
     }
 
-    pub fn read_two_bytes(&self, address: u16) -> Result<u16, CoreError> {
+    pub fn read_two_bytes(&mut self, address: u16) -> Result<u16, CoreError> {
+        if address == 0xFFFF {
+            self.record_wrap(AddressWrap::Word { address });
+        }
         let data_low = self.read_byte(address)?;
-        let data_high = self.read_byte(address + 1)?;
+        let data_high = self.read_byte(address.wrapping_add(1))?;
         Ok((data_high as u16) << 8 | data_low as u16)
     }
 
     pub fn write_byte(&mut self, address: u16, data: u8) -> Result<(), CoreError> {
-        if (address as usize) < self.ram.len() {
-            self.ram[address as usize] = data;
-            Ok(())
-        } else {
-            Err(CoreError::IndexError { index: address })
-        }
+        self.bus.write_byte(address, data)
     }
 
     pub fn write_two_bytes(&mut self, address: u16, data: u16) -> Result<(), CoreError> {
+        if address == 0xFFFF {
+            self.record_wrap(AddressWrap::Word { address });
+        }
         self.write_byte(address, (data & 0x00FF) as u8)?;
-        self.write_byte(address + 1, ((data & 0xFF00) >> 8) as u8)?;
+        self.write_byte(address.wrapping_add(1), ((data & 0xFF00) >> 8) as u8)?;
         Ok(())
     }
 
     pub fn pop_stack(&mut self) -> Result<u16, CoreError> {
         let data = self.read_two_bytes(self.stack_pointer)?;
-        match self.stack_pointer.checked_add(2) {
-            Some(x) => self.stack_pointer = x,
-            None => return Err(CoreError::StackPointerOverflow)
+        let (next, wrapped) = self.stack_pointer.overflowing_add(2);
+        if wrapped {
+            self.record_wrap(AddressWrap::StackPointer);
         }
+        self.stack_pointer = next;
         Ok(data)
     }
 
     pub fn push_stack(&mut self, data: u16) -> Result<(), CoreError>{
-        match self.stack_pointer.checked_sub(2) {
-            Some(x) => self.stack_pointer = x,
-            None => return Err(CoreError::StackPointerOverflow)
+        let (next, wrapped) = self.stack_pointer.overflowing_sub(2);
+        if wrapped {
+            self.record_wrap(AddressWrap::StackPointer);
         }
+        self.stack_pointer = next;
         self.write_two_bytes(self.stack_pointer, data)?;
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::FlatMemory;
+
+    fn flat_memory() -> Memory {
+        Memory::with_bus(Box::new(FlatMemory::new()))
+    }
+
+    #[test]
+    fn fetch_wraps_program_counter() {
+        let mut memory = flat_memory();
+        memory.write_byte(0xFFFF, 0x34).unwrap();
+        memory.write_byte(0x0000, 0x12).unwrap();
+        memory.program_counter = 0xFFFF;
+        assert_eq!(memory.fetch_two_bytes().unwrap(), 0x1234);
+        assert_eq!(memory.program_counter, 0x0001);
+    }
+
+    #[test]
+    fn word_access_wraps() {
+        let mut memory = flat_memory();
+        memory.write_two_bytes(0xFFFF, 0xBEEF).unwrap();
+        assert_eq!(memory.read_byte(0xFFFF).unwrap(), 0xEF);
+        assert_eq!(memory.read_byte(0x0000).unwrap(), 0xBE);
+        assert_eq!(memory.read_two_bytes(0xFFFF).unwrap(), 0xBEEF);
+    }
+
+    #[test]
+    fn stack_wraps() {
+        let mut memory = flat_memory();
+        memory.stack_pointer = 0x0001;
+        memory.push_stack(0xABCD).unwrap();
+        assert_eq!(memory.stack_pointer, 0xFFFF);
+        assert_eq!(memory.read_byte(0xFFFF).unwrap(), 0xCD);
+        assert_eq!(memory.read_byte(0x0000).unwrap(), 0xAB);
+        assert_eq!(memory.pop_stack().unwrap(), 0xABCD);
+        assert_eq!(memory.stack_pointer, 0x0001);
+    }
+
+    #[test]
+    fn wraps_reported_when_enabled() {
+        let mut memory = flat_memory();
+        memory.program_counter = 0xFFFF;
+        memory.fetch_byte().unwrap();
+        assert!(memory.take_wraps().is_empty());
+
+        memory.set_wrap_reporting(true);
+        memory.program_counter = 0xFFFF;
+        memory.fetch_byte().unwrap();
+        memory.stack_pointer = 0x0000;
+        memory.push_stack(0x1234).unwrap();
+        assert_eq!(memory.take_wraps(), vec![
+            AddressWrap::ProgramCounter,
+            AddressWrap::StackPointer,
+        ]);
+        memory.read_two_bytes(0xFFFF).unwrap();
+        assert_eq!(memory.take_wraps(), vec![AddressWrap::Word { address: 0xFFFF }]);
+        assert!(memory.take_wraps().is_empty());
+    }
+
+    #[test]
+    fn reported_wraps_are_capped() {
+        let mut memory = flat_memory();
+        memory.set_wrap_reporting(true);
+        for data in 0..MAX_WRAPS as u16 + 10 {
+            memory.program_counter = 0xFFFF;
+            memory.fetch_byte().unwrap();
+            memory.write_two_bytes(0xFFFF, data).unwrap();
+        }
+        let wraps = memory.take_wraps();
+        assert_eq!(wraps.len(), MAX_WRAPS);
+        assert_eq!(wraps.last(), Some(&AddressWrap::Word { address: 0xFFFF }));
+    }
+}
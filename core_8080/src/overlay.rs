@@ -0,0 +1,170 @@
+use std::str::FromStr;
+use crate::core_error::CoreError;
+use crate::framebuffer::{Framebuffer, SCREEN_WIDTH, SCREEN_HEIGHT};
+use crate::machine_config::Overlay;
+
+pub type Rgb = [u8; 3];
+
+const WHITE: Rgb = [0xFF, 0xFF, 0xFF];
+const RED: Rgb = [0xFF, 0x20, 0x20];
+const GREEN: Rgb = [0x20, 0xFF, 0x20];
+const YELLOW: Rgb = [0xFF, 0xFF, 0x20];
+const CYAN: Rgb = [0x20, 0xFF, 0xFF];
+const MAGENTA: Rgb = [0xFF, 0x20, 0xFF];
+
+// A strip of cellophane covering screen rows top..=bottom and columns left..=right
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Band {
+    pub top: usize,
+    pub bottom: usize,
+    pub left: usize,
+    pub right: usize,
+    pub colour: Rgb,
+}
+
+impl Band {
+    pub fn rows(top: usize, bottom: usize, colour: Rgb) -> Self {
+        Self { top, bottom, left: 0, right: SCREEN_WIDTH - 1, colour }
+    }
+
+    fn contains(&self, x: usize, y: usize) -> bool {
+        (self.top..=self.bottom).contains(&y) && (self.left..=self.right).contains(&x)
+    }
+}
+
+// Tints lit pixels by the band over them. Where bands overlap the last one wins, and pixels
+// outside every band stay white.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ColourOverlay {
+    pub bands: Vec<Band>,
+}
+
+impl ColourOverlay {
+    pub fn new(bands: Vec<Band>) -> Self {
+        Self { bands }
+    }
+
+    // Stock Midway upright: red over the UFO, green over the player, the shields and the
+    // reserve ships at the bottom left, leaving the credit count white
+    pub fn midway() -> Self {
+        Self::new(vec![
+            Band::rows(32, 63, RED),
+            Band::rows(184, 239, GREEN),
+            Band { top: 240, bottom: 255, left: 16, right: 133, colour: GREEN },
+        ])
+    }
+
+    // Taito's strips colour the invader rows as well
+    pub fn taito() -> Self {
+        Self::new(vec![
+            Band::rows(32, 63, RED),
+            Band::rows(64, 95, MAGENTA),
+            Band::rows(96, 135, CYAN),
+            Band::rows(136, 183, YELLOW),
+            Band::rows(184, 239, GREEN),
+            Band { top: 240, bottom: 255, left: 16, right: 133, colour: GREEN },
+        ])
+    }
+
+    pub fn preset(overlay: Overlay) -> Self {
+        match overlay {
+            Overlay::None => Self::default(),
+            Overlay::Midway => Self::midway(),
+            Overlay::Taito => Self::taito(),
+        }
+    }
+
+    pub fn colour_at(&self, x: usize, y: usize) -> Rgb {
+        self.bands.iter().rev()
+            .find(|band| band.contains(x, y))
+            .map_or(WHITE, |band| band.colour)
+    }
+
+    // RGBA pixels of the screen seen through the overlay
+    pub fn render(&self, screen: &Framebuffer) -> Vec<u8> {
+        let mut rgba = Vec::with_capacity(SCREEN_WIDTH * SCREEN_HEIGHT * 4);
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..SCREEN_WIDTH {
+                let [r, g, b] = if screen.is_lit(x, y) { self.colour_at(x, y) } else { [0x00; 3] };
+                rgba.extend_from_slice(&[r, g, b, 0xFF]);
+            }
+        }
+        rgba
+    }
+}
+
+// Parses bands such as "32-63:ff2020,240-255/16-133:20ff20": rows, optionally columns after
+// a slash, then the colour as hex RGB
+impl FromStr for ColourOverlay {
+    type Err = CoreError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut bands = Vec::new();
+        for text in s.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let invalid = || CoreError::OverlayError { band: text.to_string() };
+            let (area, colour) = text.split_once(':').ok_or_else(invalid)?;
+            let (rows, columns) = match area.split_once('/') {
+                Some((rows, columns)) => (rows, Some(columns)),
+                None => (area, None),
+            };
+            let (top, bottom) = parse_range(rows, SCREEN_HEIGHT).ok_or_else(invalid)?;
+            let (left, right) = match columns {
+                Some(columns) => parse_range(columns, SCREEN_WIDTH).ok_or_else(invalid)?,
+                None => (0, SCREEN_WIDTH - 1),
+            };
+            let colour = parse_colour(colour).ok_or_else(invalid)?;
+            bands.push(Band { top, bottom, left, right, colour });
+        }
+        Ok(Self::new(bands))
+    }
+}
+
+fn parse_range(text: &str, limit: usize) -> Option<(usize, usize)> {
+    let (start, end) = text.split_once('-')?;
+    let (start, end) = (start.trim().parse().ok()?, end.trim().parse().ok()?);
+    (start <= end && end < limit).then_some((start, end))
+}
+
+fn parse_colour(text: &str) -> Option<Rgb> {
+    let text = text.trim().trim_start_matches('#');
+    if text.len() != 6 {
+        return None
+    }
+    let value = u32::from_str_radix(text, 16).ok()?;
+    Some([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framebuffer::VRAM_SIZE;
+
+    #[test]
+    fn midway_tints_lit_pixels() {
+        let screen = Framebuffer::decode(&[0xFF; VRAM_SIZE]);
+        let rgba = ColourOverlay::midway().render(&screen);
+        let pixel = |x: usize, y: usize| rgba[(y * SCREEN_WIDTH + x) * 4..][..4].to_vec();
+        assert_eq!(pixel(100, 0), vec![0xFF, 0xFF, 0xFF, 0xFF]); // Score
+        assert_eq!(pixel(100, 40), vec![0xFF, 0x20, 0x20, 0xFF]); // UFO
+        assert_eq!(pixel(100, 200), vec![0x20, 0xFF, 0x20, 0xFF]); // Player
+        assert_eq!(pixel(20, 250), vec![0x20, 0xFF, 0x20, 0xFF]); // Reserve ships
+        assert_eq!(pixel(200, 250), vec![0xFF, 0xFF, 0xFF, 0xFF]); // Credits
+
+        let dark = ColourOverlay::midway().render(&Framebuffer::decode(&[0x00; VRAM_SIZE]));
+        assert_eq!(dark[(200 * SCREEN_WIDTH + 100) * 4..][..4], [0x00, 0x00, 0x00, 0xFF]);
+    }
+
+    #[test]
+    fn user_bands() {
+        let overlay: ColourOverlay = "32-63:ff2020, 240-255/16-133:#20ff20".parse().unwrap();
+        assert_eq!(overlay.bands, vec![
+            Band::rows(32, 63, RED),
+            Band { top: 240, bottom: 255, left: 16, right: 133, colour: GREEN },
+        ]);
+        assert_eq!(overlay.colour_at(0, 0), WHITE);
+        assert!("32-63".parse::<ColourOverlay>().is_err());
+        assert!("63-32:ffffff".parse::<ColourOverlay>().is_err());
+        assert!("0-256:ffffff".parse::<ColourOverlay>().is_err());
+        assert!("0-10:fff".parse::<ColourOverlay>().is_err());
+    }
+}
@@ -0,0 +1,102 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// Hardware attached to the 8080's I/O ports. A device may answer several ports, so it is
+// handed the port number on every access. Writes also carry the CPU cycle count at the start
+// of the OUT instruction for devices that timestamp what they see.
+pub trait IoDevice {
+    fn read(&mut self, _port: u8) -> u8 {
+        0x00
+    }
+
+    fn write(&mut self, _port: u8, _data: u8, _cycle: u64) {}
+}
+
+pub type SharedDevice = Rc<RefCell<dyn IoDevice>>;
+
+// Routes IN and OUT to the devices a machine registered for each port. Reads from a port with
+// no device return 0x00 and writes to one are dropped.
+pub struct Ports {
+    readers: Vec<Option<SharedDevice>>,
+    writers: Vec<Option<SharedDevice>>,
+}
+
+impl Default for Ports {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ports {
+    pub fn new() -> Self {
+        Self {
+            readers: vec![None; 256],
+            writers: vec![None; 256],
+        }
+    }
+
+    // Registers a device for reads from `read_ports` and writes to `write_ports`, replacing
+    // whatever was registered on those ports before
+    pub fn attach(&mut self, device: SharedDevice, read_ports: &[u8], write_ports: &[u8]) {
+        for &port in read_ports {
+            self.readers[port as usize] = Some(device.clone());
+        }
+        for &port in write_ports {
+            self.writers[port as usize] = Some(device.clone());
+        }
+    }
+
+    pub fn detach(&mut self, port: u8) {
+        self.readers[port as usize] = None;
+        self.writers[port as usize] = None;
+    }
+
+    pub fn read(&mut self, port: u8) -> u8 {
+        match &self.readers[port as usize] {
+            Some(device) => device.borrow_mut().read(port),
+            None => 0x00,
+        }
+    }
+
+    pub fn write(&mut self, port: u8, data: u8, cycle: u64) {
+        if let Some(device) = &self.writers[port as usize] {
+            device.borrow_mut().write(port, data, cycle);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Latch {
+        value: u8,
+    }
+
+    impl IoDevice for Latch {
+        fn read(&mut self, port: u8) -> u8 {
+            self.value.wrapping_add(port)
+        }
+
+        fn write(&mut self, _port: u8, data: u8, _cycle: u64) {
+            self.value = data;
+        }
+    }
+
+    #[test]
+    fn routes_ports_to_devices() {
+        let latch = Rc::new(RefCell::new(Latch { value: 0x10 }));
+        let mut ports = Ports::new();
+        ports.attach(latch.clone(), &[1, 2], &[7]);
+        assert_eq!(ports.read(1), 0x11);
+        assert_eq!(ports.read(2), 0x12);
+        assert_eq!(ports.read(3), 0x00);
+
+        ports.write(7, 0x40, 0);
+        ports.write(1, 0xFF, 0); // Not registered for writes
+        assert_eq!(latch.borrow().value, 0x40);
+
+        ports.detach(1);
+        assert_eq!(ports.read(1), 0x00);
+    }
+}
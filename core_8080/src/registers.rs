@@ -23,7 +23,7 @@ pub struct RegisterPair {
 
 impl RegisterPair {
     pub fn get_pair(&self) -> u16 {
-        ((self.high as u16) << 8) & (self.low as u16)
+        ((self.high as u16) << 8) | (self.low as u16)
     }
 
     pub fn set_pair(&mut self, value: u16) {
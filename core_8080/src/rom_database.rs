@@ -0,0 +1,200 @@
+use crate::core_error::CoreError;
+use crate::machine_config::{MachineConfig, Overlay};
+use crate::rom_set::{RomPart, RomSet, RomSource};
+
+// Who released the set. Only the original Midway board and Taito's TV and CV revisions are in
+// the built-in list; bootlegs and clones have to be added with RomDatabase::entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    Midway,
+    TaitoTv,
+    TaitoCv,
+}
+
+// A dump the core recognises, and the board it runs on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KnownRom {
+    pub title: String,
+    pub revision: String, // Which release of the game the dump is, e.g. "TV Version"
+    pub variant: Variant,
+    pub set: RomSet,
+    pub config: MachineConfig,
+}
+
+pub struct RomDatabase {
+    entries: Vec<KnownRom>,
+}
+
+impl Default for RomDatabase {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl RomDatabase {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    // CRC32s as listed by MAME's mw8080bw driver. SHA1s are only given where they have been
+    // checked against a dump, since a wrong one would reject good ROMs as bad dumps.
+    pub fn builtin() -> Self {
+        Self::new()
+            .entry(KnownRom {
+                title: "Space Invaders".to_string(),
+                revision: "Midway".to_string(),
+                variant: Variant::Midway,
+                set: RomSet::space_invaders(),
+                config: MachineConfig::space_invaders(),
+            })
+            .entry(KnownRom {
+                title: "Space Invaders".to_string(),
+                revision: "TV Version".to_string(),
+                variant: Variant::TaitoTv,
+                set: RomSet {
+                    name: "sitv".to_string(),
+                    parts: vec![
+                        RomPart::crc_only("tv0h.s1", 0x0000, 0x0800, 0xfef18aad),
+                        RomPart::crc_only("tv02.rp1", 0x0800, 0x0800, 0x3c759a90),
+                        RomPart::crc_only("tv03.n1", 0x1000, 0x0800, 0x0ad3657f),
+                        RomPart::crc_only("tv04.m1", 0x1800, 0x0800, 0xcd2c67f6),
+                    ],
+                },
+                config: MachineConfig::space_invaders().overlay(Overlay::Taito),
+            })
+            .entry(KnownRom {
+                title: "Space Invaders".to_string(),
+                revision: "CV Version".to_string(),
+                variant: Variant::TaitoCv,
+                set: RomSet {
+                    name: "sicv".to_string(),
+                    parts: vec![
+                        RomPart::crc_only("cv17.36", 0x0000, 0x0800, 0x3dfbe9e6),
+                        RomPart::crc_only("cv18.35", 0x0800, 0x0800, 0xbc3c82bf),
+                        RomPart::crc_only("cv19.34", 0x1000, 0x0800, 0xd202b41c),
+                        RomPart::crc_only("cv20.33", 0x1800, 0x0800, 0xc74ee7b6),
+                    ],
+                },
+                config: MachineConfig::space_invaders().overlay(Overlay::Taito),
+            })
+    }
+
+    // Adds a set, so frontends can recognise dumps the built-in list doesn't know
+    pub fn entry(mut self, known: KnownRom) -> Self {
+        self.entries.push(known);
+        self
+    }
+
+    pub fn entries(&self) -> &[KnownRom] {
+        &self.entries
+    }
+
+    // Tries every known set against the source and returns the first that loads cleanly, along
+    // with its image. When the file names match a set but the contents don't, or some of its
+    // files are there but not all of them, the bad or missing file is reported rather than
+    // calling the whole source unrecognised.
+    pub fn identify(&self, source: &RomSource) -> Result<(&KnownRom, Vec<u8>), CoreError> {
+        let mut bad_dump = None;
+        let mut missing_part = None;
+        for known in &self.entries {
+            match known.set.load(source) {
+                Ok(image) => return Ok((known, image)),
+                Err(CoreError::MissingRomError { name }) => {
+                    if missing_part.is_none() && known.set.partly_present(source) {
+                        missing_part = Some(CoreError::MissingRomError { name });
+                    }
+                },
+                Err(CoreError::RomChecksumError { name, reason }) => {
+                    let named_part = known.set.parts.iter().any(|p| p.name == name);
+                    if named_part && bad_dump.is_none() {
+                        bad_dump = Some(CoreError::RomChecksumError { name, reason });
+                    }
+                },
+                Err(err) => return Err(err),
+            }
+        }
+        Err(bad_dump.or(missing_part).unwrap_or_else(|| CoreError::UnknownRomError { name: source.to_string() }))
+    }
+
+    // Identifies an image that is already assembled in memory
+    pub fn identify_image(&self, image: &[u8]) -> Result<&KnownRom, CoreError> {
+        self.entries.iter()
+            .find(|known| known.set.verify_image(image).is_ok())
+            .ok_or_else(|| CoreError::UnknownRomError {
+                name: format!("{} byte image with CRC32 {:08x}", image.len(), crc32fast::hash(image)),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rom_set::TempDir;
+    use std::fs;
+
+    fn test_database() -> RomDatabase {
+        let part = |name: &str, address, data: &[u8]| RomPart::crc_only(name, address, data.len(), crc32fast::hash(data));
+        RomDatabase::new()
+            .entry(KnownRom {
+                title: "First".to_string(),
+                revision: "Original".to_string(),
+                variant: Variant::Midway,
+                set: RomSet {
+                    name: "first".to_string(),
+                    parts: vec![part("first.a", 0, &[1, 2]), part("first.b", 2, &[3, 4])],
+                },
+                config: MachineConfig::space_invaders(),
+            })
+            .entry(KnownRom {
+                title: "Second".to_string(),
+                revision: "TV Version".to_string(),
+                variant: Variant::TaitoTv,
+                set: RomSet {
+                    name: "second".to_string(),
+                    parts: vec![part("second.a", 0, &[5, 6]), part("second.b", 2, &[7, 8])],
+                },
+                config: MachineConfig::space_invaders().overlay(Overlay::None),
+            })
+    }
+
+    #[test]
+    fn identifies_by_file_names_and_contents() {
+        let dir = TempDir::new("rom_database");
+        fs::write(dir.join("second.a"), [5, 6]).unwrap();
+        fs::write(dir.join("second.b"), [7, 8]).unwrap();
+        let database = test_database();
+        let (known, image) = database.identify(&RomSource::Directory(dir.to_path_buf())).unwrap();
+        assert_eq!(known.title, "Second");
+        assert_eq!(known.revision, "TV Version");
+        assert_eq!(known.config.overlay, Overlay::None);
+        assert_eq!(image, vec![5, 6, 7, 8]);
+
+        fs::write(dir.join("second.b"), [7, 9]).unwrap();
+        let result = database.identify(&RomSource::Directory(dir.to_path_buf()));
+        assert!(matches!(result, Err(CoreError::RomChecksumError { name, .. }) if name == "second.b"));
+    }
+
+    #[test]
+    fn missing_part_of_a_recognised_set() {
+        let dir = TempDir::new("rom_database_missing");
+        fs::write(dir.join("first.a"), [1, 2]).unwrap();
+        let database = test_database();
+        let result = database.identify(&RomSource::Directory(dir.to_path_buf()));
+        assert!(matches!(result, Err(CoreError::MissingRomError { name }) if name == "first.b"));
+
+        fs::remove_file(dir.join("first.a")).unwrap();
+        fs::write(dir.join("other.bin"), [1, 2]).unwrap();
+        let result = database.identify(&RomSource::Directory(dir.to_path_buf()));
+        assert!(matches!(result, Err(CoreError::UnknownRomError { .. })));
+    }
+
+    #[test]
+    fn identifies_images() {
+        let database = test_database();
+        assert_eq!(database.identify_image(&[1, 2, 3, 4]).unwrap().title, "First");
+        assert!(matches!(database.identify_image(&[1, 2, 3, 5]), Err(CoreError::UnknownRomError { .. })));
+        assert!(matches!(RomDatabase::builtin().identify_image(&[0; 0x2000]), Err(CoreError::UnknownRomError { .. })));
+    }
+}
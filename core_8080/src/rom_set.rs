@@ -0,0 +1,359 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use crate::core_error::CoreError;
+
+// One chip of a ROM set, with the checksums of the known good dump
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomPart {
+    pub name: String,
+    pub address: u16,
+    pub size: usize,
+    pub crc32: u32,
+    pub sha1: Option<String>, // Not every dump in the database has a known SHA1
+}
+
+impl RomPart {
+    pub fn new(name: &str, address: u16, size: usize, crc32: u32, sha1: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            address,
+            size,
+            crc32,
+            sha1: Some(sha1.to_string()),
+        }
+    }
+
+    pub fn crc_only(name: &str, address: u16, size: usize, crc32: u32) -> Self {
+        Self {
+            name: name.to_string(),
+            address,
+            size,
+            crc32,
+            sha1: None,
+        }
+    }
+
+    fn verify(&self, source: &str, data: &[u8]) -> Result<(), CoreError> {
+        if data.len() != self.size {
+            return Err(CoreError::RomChecksumError {
+                name: source.to_string(),
+                reason: format!("expected {} bytes, found {}", self.size, data.len()),
+            })
+        }
+        let crc32 = crc32fast::hash(data);
+        if crc32 != self.crc32 {
+            return Err(CoreError::RomChecksumError {
+                name: source.to_string(),
+                reason: format!("expected CRC32 {:08x}, found {:08x}", self.crc32, crc32),
+            })
+        }
+        if let Some(expected) = &self.sha1 {
+            let sha1 = sha1_smol::Sha1::from(data).digest().to_string();
+            if &sha1 != expected {
+                return Err(CoreError::RomChecksumError {
+                    name: source.to_string(),
+                    reason: format!("expected SHA1 {}, found {}", expected, sha1),
+                })
+            }
+        }
+        Ok(())
+    }
+}
+
+// Where to find the parts of a set
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RomSource {
+    Directory(PathBuf),
+    Zip(PathBuf),
+    Files(Vec<PathBuf>),
+}
+
+impl RomSource {
+    // Picks the source type from the path: a directory, a .zip archive or a single file
+    pub fn from_path(path: &Path) -> Self {
+        let is_zip = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("zip"));
+        if path.is_dir() {
+            RomSource::Directory(path.to_path_buf())
+        } else if is_zip {
+            RomSource::Zip(path.to_path_buf())
+        } else {
+            RomSource::Files(vec![path.to_path_buf()])
+        }
+    }
+
+    // The names of the files the source holds. A source that can't be read has none.
+    fn file_names(&self) -> Vec<String> {
+        match self {
+            RomSource::Directory(dir) => fs::read_dir(dir)
+                .map(|entries| entries.filter_map(|e| e.ok().map(|e| e.file_name().to_string_lossy().into_owned())).collect())
+                .unwrap_or_default(),
+            RomSource::Zip(path) => File::open(path).ok()
+                .and_then(|file| zip::ZipArchive::new(file).ok())
+                .map(|archive| archive.file_names().map(str::to_string).collect())
+                .unwrap_or_default(),
+            RomSource::Files(paths) => paths.iter().map(|p| p.display().to_string()).collect(),
+        }
+    }
+}
+
+impl fmt::Display for RomSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomSource::Directory(path) | RomSource::Zip(path) => write!(f, "{}", path.display()),
+            RomSource::Files(paths) => {
+                let names: Vec<String> = paths.iter().map(|p| p.display().to_string()).collect();
+                write!(f, "{}", names.join(", "))
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomSet {
+    pub name: String,
+    pub parts: Vec<RomPart>,
+}
+
+impl RomSet {
+    // Midway Space Invaders, four 2 KiB chips at 0000/0800/1000/1800
+    pub fn space_invaders() -> Self {
+        Self {
+            name: "invaders".to_string(),
+            parts: vec![
+                RomPart::new("invaders.h", 0x0000, 0x0800, 0x734f5ad8, "ff6200af4c9110d8181249cbcef1a8a40fa40b7f"),
+                RomPart::new("invaders.g", 0x0800, 0x0800, 0x6bfaca4a, "16f48649b531bdef8c2d1446c429b5f414524350"),
+                RomPart::new("invaders.f", 0x1000, 0x0800, 0x0ccead96, "537aef03468f63c5b9e11dd61e253f7ae17d9743"),
+                RomPart::new("invaders.e", 0x1800, 0x0800, 0x14e538b0, "1d6ca0c99f9df71e2990b610deb9d7da0125e2d8"),
+            ],
+        }
+    }
+
+    // Bytes from address 0 to the end of the highest part
+    pub fn image_size(&self) -> usize {
+        self.parts.iter().map(|p| p.address as usize + p.size).max().unwrap_or(0)
+    }
+
+    // Reads every part from the source, verifies it and returns the assembled image ready for
+    // CPU::load_rom. A single file the size of the whole set is split and checked part by part.
+    pub fn load(&self, source: &RomSource) -> Result<Vec<u8>, CoreError> {
+        let files = self.read_parts(source)?;
+        let mut image = vec![0; self.image_size()];
+        for (part, (file_name, data)) in self.parts.iter().zip(files) {
+            part.verify(&file_name, &data)?;
+            let start = part.address as usize;
+            image[start..start + part.size].copy_from_slice(&data);
+        }
+        Ok(image)
+    }
+
+    // Whether the source holds at least one of the set's files, going by name alone
+    pub(crate) fn partly_present(&self, source: &RomSource) -> bool {
+        let names = source.file_names();
+        self.parts.iter().any(|part| names.iter().any(|name| file_name_matches(Path::new(name), &part.name)))
+    }
+
+    // Checks an image that was already assembled, e.g. one loaded from a single file
+    pub fn verify_image(&self, image: &[u8]) -> Result<(), CoreError> {
+        if image.len() != self.image_size() {
+            return Err(CoreError::RomChecksumError {
+                name: self.name.clone(),
+                reason: format!("expected {} bytes, found {}", self.image_size(), image.len()),
+            })
+        }
+        for part in &self.parts {
+            let start = part.address as usize;
+            part.verify(&part.name, &image[start..start + part.size])?;
+        }
+        Ok(())
+    }
+
+    // The data for each part in order, paired with the name to report if it is wrong
+    fn read_parts(&self, source: &RomSource) -> Result<Vec<(String, Vec<u8>)>, CoreError> {
+        match source {
+            RomSource::Directory(dir) => self.parts.iter()
+                .map(|part| {
+                    let path = find_file(dir, &part.name)?;
+                    Ok((part.name.clone(), read_file(&path)?))
+                })
+                .collect(),
+            RomSource::Zip(path) => self.read_zip(path),
+            RomSource::Files(paths) => {
+                if let [path] = paths.as_slice() {
+                    let data = read_file(path)?;
+                    if data.len() == self.image_size() && self.parts.len() > 1 {
+                        return Ok(self.split_image(path, &data))
+                    }
+                }
+                self.parts.iter()
+                    .map(|part| {
+                        let path = paths.iter()
+                            .find(|p| file_name_matches(p, &part.name))
+                            .ok_or_else(|| CoreError::MissingRomError { name: part.name.clone() })?;
+                        Ok((part.name.clone(), read_file(path)?))
+                    })
+                    .collect()
+            },
+        }
+    }
+
+    fn split_image(&self, path: &Path, data: &[u8]) -> Vec<(String, Vec<u8>)> {
+        self.parts.iter()
+            .map(|part| {
+                let start = part.address as usize;
+                let name = format!("{} at {:#06x} ({})", path.display(), part.address, part.name);
+                (name, data[start..start + part.size].to_vec())
+            })
+            .collect()
+    }
+
+    fn read_zip(&self, path: &Path) -> Result<Vec<(String, Vec<u8>)>, CoreError> {
+        let zip_error = |err: zip::result::ZipError| CoreError::RomReadError {
+            name: path.display().to_string(),
+            reason: err.to_string(),
+        };
+        let file = File::open(path).map_err(|err| CoreError::RomReadError {
+            name: path.display().to_string(),
+            reason: err.to_string(),
+        })?;
+        let mut archive = zip::ZipArchive::new(file).map_err(zip_error)?;
+        let mut parts = Vec::new();
+        for part in &self.parts {
+            let index = (0..archive.len())
+                .find(|&i| archive.name_for_index(i).is_some_and(|n| file_name_matches(Path::new(n), &part.name)))
+                .ok_or_else(|| CoreError::MissingRomError { name: part.name.clone() })?;
+            let mut entry = archive.by_index(index).map_err(zip_error)?;
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data).map_err(|err| CoreError::RomReadError {
+                name: part.name.clone(),
+                reason: err.to_string(),
+            })?;
+            parts.push((part.name.clone(), data));
+        }
+        Ok(parts)
+    }
+}
+
+// Dumps turn up in both upper and lower case, so names are matched ignoring case
+fn file_name_matches(path: &Path, name: &str) -> bool {
+    path.file_name().is_some_and(|f| f.to_string_lossy().eq_ignore_ascii_case(name))
+}
+
+fn find_file(dir: &Path, name: &str) -> Result<PathBuf, CoreError> {
+    let entries = fs::read_dir(dir).map_err(|err| CoreError::RomReadError {
+        name: dir.display().to_string(),
+        reason: err.to_string(),
+    })?;
+    entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .find(|path| file_name_matches(path, name))
+        .ok_or_else(|| CoreError::MissingRomError { name: name.to_string() })
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, CoreError> {
+    fs::read(path).map_err(|err| CoreError::RomReadError {
+        name: path.display().to_string(),
+        reason: err.to_string(),
+    })
+}
+
+// A scratch directory for tests, deleted again when dropped, even if the test panics
+#[cfg(test)]
+pub(crate) struct TempDir(PathBuf);
+
+#[cfg(test)]
+impl TempDir {
+    pub(crate) fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("core_8080_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    // Two fake 4 byte chips, so the tests don't need the real dumps
+    fn test_set() -> RomSet {
+        RomSet {
+            name: "test".to_string(),
+            parts: vec![
+                RomPart::new("test.a", 0x0000, 4, crc32fast::hash(&[1, 2, 3, 4]),
+                    &sha1_smol::Sha1::from([1, 2, 3, 4]).digest().to_string()),
+                RomPart::new("test.b", 0x0004, 4, crc32fast::hash(&[5, 6, 7, 8]),
+                    &sha1_smol::Sha1::from([5, 6, 7, 8]).digest().to_string()),
+            ],
+        }
+    }
+
+    #[test]
+    fn load_from_directory() {
+        let dir = TempDir::new("rom_dir");
+        fs::write(dir.join("TEST.A"), [1, 2, 3, 4]).unwrap();
+        fs::write(dir.join("test.b"), [5, 6, 7, 8]).unwrap();
+        let image = test_set().load(&RomSource::from_path(&dir)).unwrap();
+        assert_eq!(image, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn load_from_zip() {
+        let dir = TempDir::new("rom_zip");
+        let path = dir.join("test.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        zip.start_file("test.b", options).unwrap();
+        zip.write_all(&[5, 6, 7, 8]).unwrap();
+        zip.start_file("test.a", options).unwrap();
+        zip.write_all(&[1, 2, 3, 4]).unwrap();
+        zip.finish().unwrap();
+        let image = test_set().load(&RomSource::from_path(&path)).unwrap();
+        assert_eq!(image, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn load_combined_image() {
+        let dir = TempDir::new("rom_combined");
+        let path = dir.join("test.bin");
+        fs::write(&path, [1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+        let image = test_set().load(&RomSource::from_path(&path)).unwrap();
+        assert_eq!(image, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn missing_part_reported_by_name() {
+        let dir = TempDir::new("rom_missing");
+        let path = dir.join("test.a");
+        fs::write(&path, [1, 2, 3, 4]).unwrap();
+        let result = test_set().load(&RomSource::Files(vec![path]));
+        assert!(matches!(result, Err(CoreError::MissingRomError { name }) if name == "test.b"));
+    }
+
+    #[test]
+    fn bad_dump_reported_by_name() {
+        let dir = TempDir::new("rom_bad");
+        fs::write(dir.join("test.a"), [1, 2, 3, 4]).unwrap();
+        fs::write(dir.join("test.b"), [5, 6, 7, 9]).unwrap();
+        let result = test_set().load(&RomSource::Directory(dir.to_path_buf()));
+        assert!(matches!(result, Err(CoreError::RomChecksumError { name, .. }) if name == "test.b"));
+    }
+}
@@ -1,13 +1,62 @@
+use crate::ports::IoDevice;
+
+// Space Invaders wiring of the shifter ports
+pub const SHIFT_OFFSET_PORT: u8 = 0x02;
+pub const SHIFT_RESULT_PORT: u8 = 0x03;
+pub const SHIFT_DATA_PORT: u8 = 0x04;
+
+// Set in the offset write on boards with the reversing buffer to mirror the result
+const REVERSE_BIT: u8 = 0x08;
+
+// Where a board wires the shifter. Some boards add a buffer that can hand back the result
+// bit-reversed, either when bit 3 of the offset write is set or always on a second port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShifterPorts {
+    pub offset: u8,
+    pub data: u8,
+    pub result: u8,
+    pub reversible: bool,
+    pub reversed_result: Option<u8>,
+}
+
+impl ShifterPorts {
+    pub fn space_invaders() -> Self {
+        Self {
+            offset: SHIFT_OFFSET_PORT,
+            data: SHIFT_DATA_PORT,
+            result: SHIFT_RESULT_PORT,
+            reversible: false,
+            reversed_result: None,
+        }
+    }
+
+    pub fn read_ports(&self) -> Vec<u8> {
+        std::iter::once(self.result).chain(self.reversed_result).collect()
+    }
+
+    pub fn write_ports(&self) -> Vec<u8> {
+        vec![self.offset, self.data]
+    }
+}
+
 pub struct ShiftRegister {
     register: u16,
     offset: u8,
+    reverse: bool,
+    ports: ShifterPorts,
 }
 
 impl ShiftRegister {
     pub fn new() -> Self {
+        Self::with_ports(ShifterPorts::space_invaders())
+    }
+
+    pub fn with_ports(ports: ShifterPorts) -> Self {
         Self {
             register: 0x0000,
             offset: 0x00,
+            reverse: false,
+            ports,
         }
     }
 
@@ -15,12 +64,62 @@ impl ShiftRegister {
         self.register >>= 8;
         self.register |= (data as u16) << 8;
     }
-    
+
     pub fn set_offset(&mut self, offset: u8) {
         self.offset = offset & 0x07;
+        self.reverse = self.ports.reversible && offset & REVERSE_BIT != 0;
     }
 
     pub fn get_shift(&self) -> u8 {
         (((self.register << self.offset) & 0xFF00) >> 8) as u8
     }
-}
\ No newline at end of file
+}
+
+impl Default for ShiftRegister {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IoDevice for ShiftRegister {
+    fn read(&mut self, port: u8) -> u8 {
+        if port == self.ports.result {
+            if self.reverse { self.get_shift().reverse_bits() } else { self.get_shift() }
+        } else if Some(port) == self.ports.reversed_result {
+            self.get_shift().reverse_bits()
+        } else {
+            0x00
+        }
+    }
+
+    fn write(&mut self, port: u8, data: u8, _cycle: u64) {
+        if port == self.ports.offset {
+            self.set_offset(data);
+        } else if port == self.ports.data {
+            self.load(data);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reversible_result() {
+        let ports = ShifterPorts { reversible: true, reversed_result: Some(0x00), ..ShifterPorts::space_invaders() };
+        let mut shifter = ShiftRegister::with_ports(ports);
+        shifter.write(SHIFT_DATA_PORT, 0x00, 0);
+        shifter.write(SHIFT_DATA_PORT, 0x03, 0);
+        shifter.write(SHIFT_OFFSET_PORT, 0x02, 0);
+        assert_eq!(shifter.read(SHIFT_RESULT_PORT), 0x0C);
+        assert_eq!(shifter.read(0x00), 0x30);
+        shifter.write(SHIFT_OFFSET_PORT, REVERSE_BIT | 0x02, 0);
+        assert_eq!(shifter.read(SHIFT_RESULT_PORT), 0x30);
+
+        let mut plain = ShiftRegister::new();
+        plain.write(SHIFT_DATA_PORT, 0x03, 0);
+        plain.write(SHIFT_OFFSET_PORT, REVERSE_BIT, 0);
+        assert_eq!(plain.read(SHIFT_RESULT_PORT), 0x03);
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::VecDeque;
+use crate::ports::IoDevice;
+
+pub const SOUND_PORT_1: u8 = 0x03;
+pub const SOUND_PORT_2: u8 = 0x05;
+
+const AMPLIFIER_BIT: u8 = 0x20; // Port 3
+const FLIP_SCREEN_BIT: u8 = 0x20; // Port 5
+
+// Far more than a frame produces. Past this the oldest events are dropped, so latches nobody
+// drains can't grow without bound.
+const MAX_QUEUED_EVENTS: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sound {
+    Ufo, // Repeats for as long as the bit is held
+    Shot,
+    PlayerDeath,
+    InvaderHit,
+    ExtraLife,
+    Fleet1,
+    Fleet2,
+    Fleet3,
+    Fleet4,
+    UfoHit,
+}
+
+// Sound triggers by port and bit
+const PORT_1_SOUNDS: [(u8, Sound); 5] = [
+    (0x01, Sound::Ufo),
+    (0x02, Sound::Shot),
+    (0x04, Sound::PlayerDeath),
+    (0x08, Sound::InvaderHit),
+    (0x10, Sound::ExtraLife),
+];
+const PORT_2_SOUNDS: [(u8, Sound); 5] = [
+    (0x01, Sound::Fleet1),
+    (0x02, Sound::Fleet2),
+    (0x04, Sound::Fleet3),
+    (0x08, Sound::Fleet4),
+    (0x10, Sound::UfoHit),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundEvent {
+    Start { sound: Sound, cycle: u64 },
+    Stop { sound: Sound, cycle: u64 },
+}
+
+// Latches the sound ports and turns bit edges into start and stop events, so frontends never
+// have to decode the ports themselves. Events queue up until take_events drains them. Machine
+// does that every frame, and anyone attaching the latches themselves has to as well.
+pub struct SoundLatches {
+    port1: u8,
+    port2: u8,
+    events: VecDeque<SoundEvent>,
+}
+
+impl Default for SoundLatches {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SoundLatches {
+    pub fn new() -> Self {
+        Self {
+            port1: 0x00,
+            port2: 0x00,
+            events: VecDeque::new(),
+        }
+    }
+
+    pub fn take_events(&mut self) -> Vec<SoundEvent> {
+        self.events.drain(..).collect()
+    }
+
+    pub fn is_playing(&self, sound: Sound) -> bool {
+        PORT_1_SOUNDS.iter().any(|&(bit, s)| s == sound && self.port1 & bit != 0)
+            || PORT_2_SOUNDS.iter().any(|&(bit, s)| s == sound && self.port2 & bit != 0)
+    }
+
+    pub fn amplifier_enabled(&self) -> bool {
+        self.port1 & AMPLIFIER_BIT != 0
+    }
+
+    // Cocktail cabinets flip the screen for player 2
+    pub fn screen_flipped(&self) -> bool {
+        self.port2 & FLIP_SCREEN_BIT != 0
+    }
+
+    fn latch(events: &mut VecDeque<SoundEvent>, sounds: &[(u8, Sound)], old: u8, new: u8, cycle: u64) {
+        for &(bit, sound) in sounds {
+            let event = match (old & bit != 0, new & bit != 0) {
+                (false, true) => SoundEvent::Start { sound, cycle },
+                (true, false) => SoundEvent::Stop { sound, cycle },
+                _ => continue,
+            };
+            if events.len() == MAX_QUEUED_EVENTS {
+                events.pop_front();
+            }
+            events.push_back(event);
+        }
+    }
+}
+
+impl IoDevice for SoundLatches {
+    fn write(&mut self, port: u8, data: u8, cycle: u64) {
+        match port {
+            SOUND_PORT_1 => {
+                Self::latch(&mut self.events, &PORT_1_SOUNDS, self.port1, data, cycle);
+                self.port1 = data;
+            },
+            SOUND_PORT_2 => {
+                Self::latch(&mut self.events, &PORT_2_SOUNDS, self.port2, data, cycle);
+                self.port2 = data;
+            },
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edges_become_events() {
+        let mut sound = SoundLatches::new();
+        sound.write(SOUND_PORT_1, 0x22, 100); // Shot and amplifier
+        sound.write(SOUND_PORT_1, 0x22, 200); // No edges
+        sound.write(SOUND_PORT_2, 0x01, 300);
+        sound.write(SOUND_PORT_1, 0x29, 400); // Shot off, UFO and invader hit on
+        assert_eq!(sound.take_events(), vec![
+            SoundEvent::Start { sound: Sound::Shot, cycle: 100 },
+            SoundEvent::Start { sound: Sound::Fleet1, cycle: 300 },
+            SoundEvent::Start { sound: Sound::Ufo, cycle: 400 },
+            SoundEvent::Stop { sound: Sound::Shot, cycle: 400 },
+            SoundEvent::Start { sound: Sound::InvaderHit, cycle: 400 },
+        ]);
+        assert!(sound.take_events().is_empty());
+        assert!(sound.is_playing(Sound::Ufo));
+        assert!(!sound.is_playing(Sound::Shot));
+        assert!(sound.amplifier_enabled());
+        assert!(!sound.screen_flipped());
+    }
+
+    #[test]
+    fn undrained_events_are_capped() {
+        let mut sound = SoundLatches::new();
+        for cycle in 0..MAX_QUEUED_EVENTS as u64 {
+            sound.write(SOUND_PORT_1, (cycle as u8 & 0x01) ^ 0x01, cycle);
+        }
+        sound.write(SOUND_PORT_2, 0x10, 5000);
+        let events = sound.take_events();
+        assert_eq!(events.len(), MAX_QUEUED_EVENTS);
+        assert_eq!(events[0], SoundEvent::Stop { sound: Sound::Ufo, cycle: 1 });
+        assert_eq!(events[MAX_QUEUED_EVENTS - 1], SoundEvent::Start { sound: Sound::UfoHit, cycle: 5000 });
+    }
+
+    #[test]
+    fn flip_and_amplifier_are_not_sounds() {
+        let mut sound = SoundLatches::new();
+        sound.write(SOUND_PORT_1, AMPLIFIER_BIT, 0);
+        sound.write(SOUND_PORT_2, FLIP_SCREEN_BIT, 0);
+        assert!(sound.take_events().is_empty());
+        assert!(sound.screen_flipped());
+    }
+}
@@ -0,0 +1,22 @@
+// Extra T-states spent by a conditional CALL or RET whose condition is met
+pub const BRANCH_TAKEN_CYCLES: u32 = 6;
+
+// T-states burned by each tick() while halted, waiting for an interrupt
+pub const HALTED_CYCLES: u32 = 4;
+
+// 2 MHz CPU clock divided into 60 Hz video frames
+pub const CPU_CLOCK_HZ: u32 = 2_000_000;
+pub const FRAMES_PER_SECOND: u32 = 60;
+pub const CYCLES_PER_FRAME: u32 = CPU_CLOCK_HZ / FRAMES_PER_SECOND;
+
+// The video counter runs 262 lines a frame, 224 of them visible. The Space Invaders board pulls
+// RST 1 when the beam reaches the middle of the screen and RST 2 when it enters VBlank, so the
+// game can redraw whichever half the beam isn't on.
+pub const SCANLINES_PER_FRAME: u32 = 262;
+pub const MID_SCREEN_LINE: u32 = 96;
+pub const VBLANK_LINE: u32 = 224;
+
+// CPU cycles from the start of the frame until the beam reaches `line`
+pub const fn line_cycle(line: u32) -> u32 {
+    line * CYCLES_PER_FRAME / SCANLINES_PER_FRAME
+}
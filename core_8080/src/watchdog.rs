@@ -0,0 +1,82 @@
+use crate::ports::IoDevice;
+use crate::timing::CYCLES_PER_FRAME;
+
+pub const WATCHDOG_PORT: u8 = 0x06;
+
+// The Midway board resets the CPU after 255 frames without a watchdog write
+const TIMEOUT_FRAMES: u64 = 255;
+
+// Any write to the watchdog port restarts the countdown. The machine feeds it elapsed cycles
+// and resets the CPU whenever elapse() reports a timeout.
+pub struct Watchdog {
+    timeout_cycles: u64,
+    elapsed: u64,
+    resets: u32,
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Watchdog {
+    pub fn new() -> Self {
+        Self::with_timeout(TIMEOUT_FRAMES * CYCLES_PER_FRAME as u64)
+    }
+
+    pub fn with_timeout(timeout_cycles: u64) -> Self {
+        Self {
+            timeout_cycles,
+            elapsed: 0,
+            resets: 0,
+        }
+    }
+
+    // Returns true when the timeout has just expired and the machine must be reset
+    pub fn elapse(&mut self, cycles: u32) -> bool {
+        self.elapsed += cycles as u64;
+        if self.elapsed < self.timeout_cycles {
+            return false
+        }
+        self.elapsed = 0;
+        self.resets += 1;
+        true
+    }
+
+    // Number of resets fired since power on, for hang detection in soak tests
+    pub fn resets(&self) -> u32 {
+        self.resets
+    }
+}
+
+impl IoDevice for Watchdog {
+    fn write(&mut self, _port: u8, _data: u8, _cycle: u64) {
+        self.elapsed = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kicked_watchdog_never_fires() {
+        let mut watchdog = Watchdog::with_timeout(100);
+        for _ in 0..10 {
+            assert!(!watchdog.elapse(60));
+            watchdog.write(WATCHDOG_PORT, 0x00, 0);
+        }
+        assert_eq!(watchdog.resets(), 0);
+    }
+
+    #[test]
+    fn starved_watchdog_fires_and_restarts() {
+        let mut watchdog = Watchdog::with_timeout(100);
+        assert!(!watchdog.elapse(99));
+        assert!(watchdog.elapse(1));
+        assert!(!watchdog.elapse(99));
+        assert!(watchdog.elapse(1));
+        assert_eq!(watchdog.resets(), 2);
+    }
+}
@@ -0,0 +1,86 @@
+use core_8080::{ButtonState, Control, Inputs};
+use sdl2::keyboard::Keycode;
+
+// Something that can hold a control down
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Key(Keycode),
+    Pad(u32), // Controller instance id
+}
+
+// Which sources are holding each control. A control is only let go of once every key and pad
+// holding it has been released, so e.g. a keyboard fire key can't release the fire button a pad
+// is still holding.
+#[derive(Debug, Default)]
+pub struct HeldControls {
+    held: Vec<(Control, Source)>,
+}
+
+impl HeldControls {
+    pub fn press(&mut self, control: Control, source: Source, inputs: &mut Inputs) {
+        if self.held.contains(&(control, source)) {
+            return
+        }
+        if !self.is_held(control) {
+            inputs.set(control, ButtonState::Pressed);
+        }
+        self.held.push((control, source));
+    }
+
+    pub fn release(&mut self, control: Control, source: Source, inputs: &mut Inputs) {
+        let Some(index) = self.held.iter().position(|&held| held == (control, source)) else {
+            return
+        };
+        self.held.remove(index);
+        if !self.is_held(control) {
+            inputs.set(control, ButtonState::Released);
+        }
+    }
+
+    // Lets go of everything, e.g. before pausing
+    pub fn release_all(&mut self, inputs: &mut Inputs) {
+        for (control, _) in self.held.drain(..) {
+            inputs.set(control, ButtonState::Released);
+        }
+    }
+
+    pub fn is_held(&self, control: Control) -> bool {
+        self.held.iter().any(|&(c, _)| c == control)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core_8080::IoDevice;
+
+    const P1_FIRE: u8 = 0x10; // Port 1
+
+    #[test]
+    fn released_once_every_source_lets_go() {
+        let mut inputs = Inputs::new();
+        let mut held = HeldControls::default();
+        held.press(Control::P1Fire, Source::Key(Keycode::Space), &mut inputs);
+        held.press(Control::P1Fire, Source::Pad(3), &mut inputs);
+        held.press(Control::P1Fire, Source::Key(Keycode::LCtrl), &mut inputs);
+        held.release(Control::P1Fire, Source::Key(Keycode::Space), &mut inputs);
+        held.release(Control::P1Fire, Source::Key(Keycode::LCtrl), &mut inputs);
+        assert_eq!(inputs.read(0x01) & P1_FIRE, P1_FIRE);
+
+        held.release(Control::P1Fire, Source::Pad(3), &mut inputs);
+        assert_eq!(inputs.read(0x01) & P1_FIRE, 0);
+        held.release(Control::P1Fire, Source::Pad(3), &mut inputs); // Already released
+        assert!(!held.is_held(Control::P1Fire));
+    }
+
+    #[test]
+    fn release_all() {
+        let mut inputs = Inputs::new();
+        let mut held = HeldControls::default();
+        held.press(Control::P1Fire, Source::Pad(0), &mut inputs);
+        held.press(Control::P1Left, Source::Key(Keycode::Left), &mut inputs);
+        held.release_all(&mut inputs);
+        assert_eq!(inputs.read(0x01) & 0x30, 0);
+        assert!(!held.is_held(Control::P1Left));
+    }
+}
@@ -0,0 +1,465 @@
+use crate::controls::{HeldControls, Source};
+use crate::settings::GamepadConfig;
+use core_8080::{Control, Inputs};
+use sdl2::controller::{Axis, Button, GameController};
+use sdl2::event::Event;
+use sdl2::GameControllerSubsystem;
+
+const DEFAULT_DEADZONE: i16 = 8000;
+
+// What a pad can do, relative to the player it is assigned to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PadAction {
+    Left,
+    Right,
+    Up,
+    Down,
+    Fire,
+    Start,
+    Coin,
+}
+
+const PAD_ACTIONS: [PadAction; 7] = [
+    PadAction::Left,
+    PadAction::Right,
+    PadAction::Up,
+    PadAction::Down,
+    PadAction::Fire,
+    PadAction::Start,
+    PadAction::Coin,
+];
+
+// The first pad plays player 1 and the second player 2, so a cocktail cabinet can have one each
+const PLAYERS: usize = 2;
+
+fn control_for(player: usize, action: PadAction) -> Control {
+    match (player, action) {
+        (0, PadAction::Left) => Control::P1Left,
+        (0, PadAction::Right) => Control::P1Right,
+        (0, PadAction::Up) => Control::P1Up,
+        (0, PadAction::Down) => Control::P1Down,
+        (0, PadAction::Fire) => Control::P1Fire,
+        (0, PadAction::Start) => Control::P1Start,
+        (_, PadAction::Left) => Control::P2Left,
+        (_, PadAction::Right) => Control::P2Right,
+        (_, PadAction::Up) => Control::P2Up,
+        (_, PadAction::Down) => Control::P2Down,
+        (_, PadAction::Fire) => Control::P2Fire,
+        (_, PadAction::Start) => Control::P2Start,
+        (_, PadAction::Coin) => Control::Coin,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PadBindings {
+    buttons: Vec<(Button, PadAction)>,
+    x_axis: Axis, // Stick axes, alongside whatever buttons are bound to the directions
+    y_axis: Axis,
+    deadzone: i16,
+}
+
+// D-pad and left stick to move, A or B to fire, Start to start and Back for a coin
+impl Default for PadBindings {
+    fn default() -> Self {
+        Self {
+            buttons: vec![
+                (Button::DPadLeft, PadAction::Left),
+                (Button::DPadRight, PadAction::Right),
+                (Button::DPadUp, PadAction::Up),
+                (Button::DPadDown, PadAction::Down),
+                (Button::A, PadAction::Fire),
+                (Button::B, PadAction::Fire),
+                (Button::Start, PadAction::Start),
+                (Button::Back, PadAction::Coin),
+            ],
+            x_axis: Axis::LeftX,
+            y_axis: Axis::LeftY,
+            deadzone: DEFAULT_DEADZONE,
+        }
+    }
+}
+
+impl PadBindings {
+    // Reads the [gamepad] table using SDL's names, e.g. fire = ["a", "x"] and x-axis = "leftx".
+    // Actions left out keep their default buttons.
+    pub fn from_config(config: &GamepadConfig) -> Result<Self, String> {
+        let mut bindings = Self::default();
+        let tables = [
+            (PadAction::Left, &config.left),
+            (PadAction::Right, &config.right),
+            (PadAction::Up, &config.up),
+            (PadAction::Down, &config.down),
+            (PadAction::Fire, &config.fire),
+            (PadAction::Start, &config.start),
+            (PadAction::Coin, &config.coin),
+        ];
+        for (action, names) in tables {
+            let Some(names) = names else { continue };
+            let buttons = names.iter()
+                .map(|name| Button::from_string(name).ok_or_else(|| format!("Unknown gamepad button `{}` in [gamepad]", name)))
+                .collect::<Result<Vec<Button>, String>>()?;
+            bindings.buttons.retain(|&(button, a)| a != action && !buttons.contains(&button));
+            bindings.buttons.extend(buttons.into_iter().map(|button| (button, action)));
+        }
+        let axis = |name: &str| Axis::from_string(name).ok_or_else(|| format!("Unknown gamepad axis `{}` in [gamepad]", name));
+        if let Some(name) = &config.x_axis {
+            bindings.x_axis = axis(name)?;
+        }
+        if let Some(name) = &config.y_axis {
+            bindings.y_axis = axis(name)?;
+        }
+        if let Some(deadzone) = config.deadzone {
+            if deadzone < 0 {
+                return Err(format!("Gamepad deadzone must be between 0 and {}, not {}", i16::MAX, deadzone))
+            }
+            bindings.deadzone = deadzone;
+        }
+        Ok(bindings)
+    }
+
+    fn action_for(&self, button: Button) -> Option<PadAction> {
+        self.buttons.iter().find(|&&(b, _)| b == button).map(|&(_, action)| action)
+    }
+}
+
+struct Pad {
+    id: u32, // SDL instance id
+    name: String,
+    player: Option<usize>,
+    held: Vec<Button>,
+    x: i16,
+    y: i16,
+    pressed: Vec<PadAction>,
+}
+
+impl Pad {
+    fn is_pressed(&self, action: PadAction, bindings: &PadBindings) -> bool {
+        let stick = match action {
+            PadAction::Left => self.x < -bindings.deadzone,
+            PadAction::Right => self.x > bindings.deadzone,
+            PadAction::Up => self.y < -bindings.deadzone,
+            PadAction::Down => self.y > bindings.deadzone,
+            _ => false,
+        };
+        stick || self.held.iter().any(|&button| bindings.action_for(button) == Some(action))
+    }
+
+    // Passes any actions that changed since the last update on to the inputs
+    fn update(&mut self, bindings: &PadBindings, controls: &mut HeldControls, inputs: &mut Inputs) {
+        for action in PAD_ACTIONS {
+            let pressed = self.is_pressed(action, bindings);
+            if pressed == self.pressed.contains(&action) {
+                continue
+            }
+            if pressed {
+                self.pressed.push(action);
+            } else {
+                self.pressed.retain(|&a| a != action);
+            }
+            let Some(player) = self.player else { continue };
+            let control = control_for(player, action);
+            match (action, pressed) {
+                // A press drops one coin, played out as a proper switch pulse
+                (PadAction::Coin, true) => inputs.insert_coin(),
+                (PadAction::Coin, false) => (),
+                (_, true) => controls.press(control, Source::Pad(self.id), inputs),
+                (_, false) => controls.release(control, Source::Pad(self.id), inputs),
+            }
+        }
+    }
+}
+
+// Player assignment and button state of every connected pad, kept apart from SDL's controller
+// handles. While paused the pads are still tracked but don't touch the inputs.
+pub struct Pads {
+    bindings: PadBindings,
+    pads: Vec<Pad>,
+    paused: bool,
+}
+
+impl Pads {
+    pub fn new(bindings: PadBindings) -> Self {
+        Self {
+            bindings,
+            pads: Vec::new(),
+            paused: false,
+        }
+    }
+
+    fn connect(&mut self, id: u32, name: &str) {
+        let player = self.free_player();
+        match player {
+            Some(player) => println!("{} connected for player {}", name, player + 1),
+            None => println!("{} connected, both players already have a pad", name),
+        }
+        self.pads.push(Pad { id, name: name.to_string(), player, held: Vec::new(), x: 0, y: 0, pressed: Vec::new() });
+    }
+
+    fn disconnect(&mut self, id: u32, controls: &mut HeldControls, inputs: &mut Inputs) {
+        let Some(index) = self.pads.iter().position(|pad| pad.id == id) else {
+            return
+        };
+        let pad = &mut self.pads[index];
+        pad.held.clear();
+        pad.x = 0;
+        pad.y = 0;
+        self.sync(index, controls, inputs);
+        let pad = self.pads.remove(index);
+        println!("{} disconnected", pad.name);
+
+        // Hand the player over to a pad that was waiting for one
+        let Some(player) = pad.player else { return };
+        if let Some(index) = self.pads.iter().position(|pad| pad.player.is_none()) {
+            let waiting = &mut self.pads[index];
+            waiting.player = Some(player);
+            waiting.pressed.clear();
+            println!("{} now plays player {}", waiting.name, player + 1);
+            self.sync(index, controls, inputs);
+        }
+    }
+
+    fn free_player(&self) -> Option<usize> {
+        (0..PLAYERS).find(|&player| self.pads.iter().all(|pad| pad.player != Some(player)))
+    }
+
+    fn button(&mut self, id: u32, button: Button, down: bool, controls: &mut HeldControls, inputs: &mut Inputs) {
+        let Some(index) = self.pads.iter().position(|pad| pad.id == id) else {
+            return
+        };
+        let pad = &mut self.pads[index];
+        pad.held.retain(|&b| b != button);
+        if down {
+            pad.held.push(button);
+        }
+        self.sync(index, controls, inputs);
+    }
+
+    fn axis(&mut self, id: u32, axis: Axis, value: i16, controls: &mut HeldControls, inputs: &mut Inputs) {
+        let Some(index) = self.pads.iter().position(|pad| pad.id == id) else {
+            return
+        };
+        let pad = &mut self.pads[index];
+        if axis == self.bindings.x_axis {
+            pad.x = value;
+        } else if axis == self.bindings.y_axis {
+            pad.y = value;
+        } else {
+            return
+        }
+        self.sync(index, controls, inputs);
+    }
+
+    fn sync(&mut self, index: usize, controls: &mut HeldControls, inputs: &mut Inputs) {
+        if !self.paused {
+            self.pads[index].update(&self.bindings, controls, inputs);
+        }
+    }
+
+    // Lets go of everything the pads hold, e.g. while the rebinding screen is up
+    pub fn pause(&mut self, controls: &mut HeldControls, inputs: &mut Inputs) {
+        self.paused = true;
+        for pad in self.pads.iter_mut() {
+            if let Some(player) = pad.player {
+                for action in pad.pressed.iter().filter(|&&action| action != PadAction::Coin) {
+                    controls.release(control_for(player, *action), Source::Pad(pad.id), inputs);
+                }
+            }
+            pad.pressed.clear();
+        }
+    }
+
+    // Presses whatever is still held on the pads
+    pub fn resume(&mut self, controls: &mut HeldControls, inputs: &mut Inputs) {
+        self.paused = false;
+        for index in 0..self.pads.len() {
+            self.sync(index, controls, inputs);
+        }
+    }
+}
+
+// Every connected GameController, opened at start up and as they are plugged in
+pub struct Gamepads {
+    subsystem: GameControllerSubsystem,
+    controllers: Vec<GameController>,
+    pub pads: Pads,
+}
+
+impl Gamepads {
+    pub fn new(subsystem: GameControllerSubsystem, bindings: PadBindings) -> Self {
+        let mut gamepads = Self {
+            subsystem,
+            controllers: Vec::new(),
+            pads: Pads::new(bindings),
+        };
+        let count = gamepads.subsystem.num_joysticks().unwrap_or(0);
+        for index in 0..count {
+            gamepads.open(index);
+        }
+        gamepads
+    }
+
+    fn open(&mut self, joystick_index: u32) {
+        if !self.subsystem.is_game_controller(joystick_index) {
+            return
+        }
+        let controller = match self.subsystem.open(joystick_index) {
+            Ok(controller) => controller,
+            Err(err) => {
+                eprintln!("Couldn't open gamepad {}: {}", joystick_index, err);
+                return
+            },
+        };
+        // SDL also reports pads that were already connected at start up as added
+        let id = controller.instance_id();
+        if self.controllers.iter().any(|c| c.instance_id() == id) {
+            return
+        }
+        self.pads.connect(id, &controller.name());
+        self.controllers.push(controller);
+    }
+
+    // Handles controller events, returning false for events that aren't from a controller
+    pub fn handle(&mut self, event: &Event, controls: &mut HeldControls, inputs: &mut Inputs) -> bool {
+        match *event {
+            Event::ControllerDeviceAdded { which, .. } => self.open(which),
+            Event::ControllerDeviceRemoved { which, .. } => {
+                self.controllers.retain(|c| c.instance_id() != which);
+                self.pads.disconnect(which, controls, inputs);
+            },
+            Event::ControllerButtonDown { which, button, .. } => self.pads.button(which, button, true, controls, inputs),
+            Event::ControllerButtonUp { which, button, .. } => self.pads.button(which, button, false, controls, inputs),
+            Event::ControllerAxisMotion { which, axis, value, .. } => self.pads.axis(which, axis, value, controls, inputs),
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core_8080::IoDevice;
+
+    // Space Invaders wiring: player 1 on port 1, player 2 on port 2
+    const FIRE: u8 = 0x10;
+    const LEFT: u8 = 0x20;
+    const RIGHT: u8 = 0x40;
+
+    fn setup() -> (Pads, HeldControls, Inputs) {
+        (Pads::new(PadBindings::default()), HeldControls::default(), Inputs::new())
+    }
+
+    #[test]
+    fn players_assigned_and_handed_over() {
+        let (mut pads, mut controls, mut inputs) = setup();
+        pads.connect(10, "First");
+        pads.connect(11, "Second");
+        pads.connect(12, "Spare");
+        assert_eq!(pads.pads.iter().map(|pad| pad.player).collect::<Vec<_>>(), [Some(0), Some(1), None]);
+
+        pads.button(11, Button::A, true, &mut controls, &mut inputs);
+        assert_eq!(inputs.read(0x02) & FIRE, FIRE);
+        pads.button(12, Button::DPadLeft, true, &mut controls, &mut inputs);
+        assert_eq!(inputs.read(0x01) & LEFT, 0); // The spare pad plays nobody
+
+        pads.disconnect(10, &mut controls, &mut inputs);
+        assert_eq!(pads.pads.iter().map(|pad| pad.player).collect::<Vec<_>>(), [Some(1), Some(0)]);
+        assert_eq!(inputs.read(0x01) & LEFT, LEFT); // Still held as the spare takes over
+
+        pads.disconnect(11, &mut controls, &mut inputs);
+        assert_eq!(inputs.read(0x02) & FIRE, 0);
+        pads.connect(13, "Third");
+        assert_eq!(pads.pads[1].player, Some(1));
+    }
+
+    #[test]
+    fn stick_deadzone() {
+        let (mut pads, mut controls, mut inputs) = setup();
+        pads.connect(0, "Pad");
+        pads.axis(0, Axis::LeftX, DEFAULT_DEADZONE, &mut controls, &mut inputs);
+        assert_eq!(inputs.read(0x01) & RIGHT, 0);
+        pads.axis(0, Axis::LeftX, DEFAULT_DEADZONE + 1, &mut controls, &mut inputs);
+        assert_eq!(inputs.read(0x01) & RIGHT, RIGHT);
+        pads.axis(0, Axis::LeftX, -DEFAULT_DEADZONE, &mut controls, &mut inputs);
+        assert_eq!(inputs.read(0x01) & (LEFT | RIGHT), 0);
+        pads.axis(0, Axis::LeftX, i16::MIN, &mut controls, &mut inputs);
+        assert_eq!(inputs.read(0x01) & (LEFT | RIGHT), LEFT);
+        pads.axis(0, Axis::RightX, 0, &mut controls, &mut inputs); // Not a movement axis
+        assert_eq!(inputs.read(0x01) & LEFT, LEFT);
+
+        // The D-pad and the stick both hold left
+        pads.button(0, Button::DPadLeft, true, &mut controls, &mut inputs);
+        pads.axis(0, Axis::LeftX, 0, &mut controls, &mut inputs);
+        assert_eq!(inputs.read(0x01) & LEFT, LEFT);
+        pads.button(0, Button::DPadLeft, false, &mut controls, &mut inputs);
+        assert_eq!(inputs.read(0x01) & LEFT, 0);
+
+        pads.axis(0, Axis::LeftY, -DEFAULT_DEADZONE - 1, &mut controls, &mut inputs);
+        assert!(controls.is_held(Control::P1Up));
+        assert!(!controls.is_held(Control::P1Down));
+    }
+
+    #[test]
+    fn shared_with_the_keyboard() {
+        let (mut pads, mut controls, mut inputs) = setup();
+        pads.connect(0, "Pad");
+        controls.press(Control::P1Fire, Source::Key(sdl2::keyboard::Keycode::Space), &mut inputs);
+        pads.button(0, Button::B, true, &mut controls, &mut inputs);
+        controls.release(Control::P1Fire, Source::Key(sdl2::keyboard::Keycode::Space), &mut inputs);
+        assert_eq!(inputs.read(0x01) & FIRE, FIRE);
+        pads.button(0, Button::B, false, &mut controls, &mut inputs);
+        assert_eq!(inputs.read(0x01) & FIRE, 0);
+    }
+
+    #[test]
+    fn paused_pads_leave_inputs_alone() {
+        let (mut pads, mut controls, mut inputs) = setup();
+        pads.connect(0, "Pad");
+        pads.button(0, Button::A, true, &mut controls, &mut inputs);
+        pads.pause(&mut controls, &mut inputs);
+        assert_eq!(inputs.read(0x01) & FIRE, 0);
+        pads.button(0, Button::DPadRight, true, &mut controls, &mut inputs);
+        pads.button(0, Button::Back, true, &mut controls, &mut inputs);
+        assert_eq!(inputs.read(0x01) & RIGHT, 0);
+
+        // Coming back presses what is still held, but doesn't replay the coin
+        pads.button(0, Button::Back, false, &mut controls, &mut inputs);
+        pads.resume(&mut controls, &mut inputs);
+        assert_eq!(inputs.read(0x01) & (FIRE | RIGHT), FIRE | RIGHT);
+        inputs.elapse(1);
+        assert_eq!(inputs.read(0x01) & 0x01, 0x01); // Coin switch still open
+    }
+
+    #[test]
+    fn coin_button_drops_a_coin() {
+        let (mut pads, mut controls, mut inputs) = setup();
+        pads.connect(0, "Pad");
+        pads.button(0, Button::Back, true, &mut controls, &mut inputs);
+        inputs.elapse(1);
+        assert_eq!(inputs.read(0x01) & 0x01, 0x00); // Active low
+    }
+
+    #[test]
+    fn config() {
+        let config = GamepadConfig {
+            fire: Some(vec!["x".to_string(), "back".to_string()]),
+            x_axis: Some("rightx".to_string()),
+            deadzone: Some(0),
+            ..GamepadConfig::default()
+        };
+        let bindings = PadBindings::from_config(&config).unwrap();
+        assert_eq!(bindings.action_for(Button::X), Some(PadAction::Fire));
+        assert_eq!(bindings.action_for(Button::Back), Some(PadAction::Fire)); // Moved from coin
+        assert_eq!(bindings.action_for(Button::A), None);
+        assert_eq!(bindings.action_for(Button::DPadLeft), Some(PadAction::Left));
+        assert_eq!((bindings.x_axis, bindings.y_axis, bindings.deadzone), (Axis::RightX, Axis::LeftY, 0));
+
+        let error = |config: GamepadConfig| PadBindings::from_config(&config).unwrap_err();
+        let err = error(GamepadConfig { fire: Some(vec!["trigger".to_string()]), ..GamepadConfig::default() });
+        assert!(err.contains("button `trigger`"), "{}", err);
+        let err = error(GamepadConfig { y_axis: Some("wheel".to_string()), ..GamepadConfig::default() });
+        assert!(err.contains("axis `wheel`"), "{}", err);
+        let err = error(GamepadConfig { deadzone: Some(-1), ..GamepadConfig::default() });
+        assert!(err.contains("deadzone"), "{}", err);
+    }
+}
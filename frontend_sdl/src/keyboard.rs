@@ -0,0 +1,272 @@
+use core_8080::Control;
+use sdl2::keyboard::Keycode;
+use std::collections::BTreeMap;
+
+// Every bindable control with its config file key and the name shown while rebinding
+pub const CONTROLS: [(Control, &str, &str); 14] = [
+    (Control::P1Left, "p1-left", "P1 Left"),
+    (Control::P1Right, "p1-right", "P1 Right"),
+    (Control::P1Up, "p1-up", "P1 Up"),
+    (Control::P1Down, "p1-down", "P1 Down"),
+    (Control::P1Fire, "p1-fire", "P1 Fire"),
+    (Control::P2Left, "p2-left", "P2 Left"),
+    (Control::P2Right, "p2-right", "P2 Right"),
+    (Control::P2Up, "p2-up", "P2 Up"),
+    (Control::P2Down, "p2-down", "P2 Down"),
+    (Control::P2Fire, "p2-fire", "P2 Fire"),
+    (Control::P1Start, "start1", "1 Player Start"),
+    (Control::P2Start, "start2", "2 Player Start"),
+    (Control::Coin, "coin", "Coin"),
+    (Control::Tilt, "tilt", "Tilt"),
+];
+
+// Keys the frontend keeps for itself: quitting and opening the rebinding screen
+pub const QUIT_KEY: Keycode = Keycode::Escape;
+pub const REBIND_KEY: Keycode = Keycode::F1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyBindings {
+    keys: Vec<(Control, Vec<Keycode>)>,
+}
+
+// Arcade emulator defaults: arrows and left Ctrl for player 1, D/G/R/F and A for player 2,
+// 1 and 2 to start, 5 for a coin and T to tilt
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            keys: vec![
+                (Control::P1Left, vec![Keycode::Left]),
+                (Control::P1Right, vec![Keycode::Right]),
+                (Control::P1Up, vec![Keycode::Up]),
+                (Control::P1Down, vec![Keycode::Down]),
+                (Control::P1Fire, vec![Keycode::LCtrl, Keycode::Space]),
+                (Control::P2Left, vec![Keycode::D]),
+                (Control::P2Right, vec![Keycode::G]),
+                (Control::P2Up, vec![Keycode::R]),
+                (Control::P2Down, vec![Keycode::F]),
+                (Control::P2Fire, vec![Keycode::A]),
+                (Control::P1Start, vec![Keycode::Num1]),
+                (Control::P2Start, vec![Keycode::Num2]),
+                (Control::Coin, vec![Keycode::Num5]),
+                (Control::Tilt, vec![Keycode::T]),
+            ],
+        }
+    }
+}
+
+impl KeyBindings {
+    // Reads the [keys] table, e.g. p1-fire = ["Left Ctrl", "Space"], using SDL's key names.
+    // Controls left out keep their default keys, less any the table gives to other controls.
+    // A key listed for two controls, or a control left out that loses all its keys, is an error.
+    pub fn from_config(table: &BTreeMap<String, Vec<String>>) -> Result<Self, String> {
+        let mut bindings = Self::default();
+        let mut listed: Vec<(Control, &str)> = Vec::new();
+        for (name, key_names) in table {
+            let (control, _, _) = CONTROLS.iter()
+                .find(|(_, config_name, _)| config_name == name)
+                .ok_or_else(|| format!("Unknown control `{}` in [keys]", name))?;
+            let keycodes = key_names.iter()
+                .map(|key| Keycode::from_name(key).ok_or_else(|| format!("Unknown key `{}` for {} in [keys]", key, name)))
+                .collect::<Result<Vec<Keycode>, String>>()?;
+            if let Some(reserved) = keycodes.iter().find(|&&k| k == QUIT_KEY || k == REBIND_KEY) {
+                return Err(format!("{} is reserved and can't be bound to {}", reserved.name(), name))
+            }
+            for keycode in &keycodes {
+                if let Some((_, other)) = listed.iter().find(|&&(c, _)| bindings.keys_for(c).contains(keycode)) {
+                    return Err(format!("{} is bound to both {} and {} in [keys]", keycode.name(), other, name))
+                }
+            }
+            bindings.set(*control, keycodes);
+            listed.push((*control, name));
+        }
+        let unbound = CONTROLS.iter()
+            .find(|&&(control, _, _)| bindings.keys_for(control).is_empty() && listed.iter().all(|&(c, _)| c != control));
+        if let Some((_, name, _)) = unbound {
+            return Err(format!("{} has no key left once [keys] is applied, so give it one there too", name))
+        }
+        Ok(bindings)
+    }
+
+    pub fn to_config(&self) -> BTreeMap<String, Vec<String>> {
+        CONTROLS.iter()
+            .map(|&(control, name, _)| (name.to_string(), self.keys_for(control).iter().map(|k| k.name()).collect()))
+            .collect()
+    }
+
+    pub fn keys_for(&self, control: Control) -> &[Keycode] {
+        self.keys.iter()
+            .find(|(c, _)| *c == control)
+            .map_or(&[], |(_, keys)| keys.as_slice())
+    }
+
+    pub fn control_for(&self, keycode: Keycode) -> Option<Control> {
+        self.keys.iter()
+            .find(|(_, keys)| keys.contains(&keycode))
+            .map(|&(control, _)| control)
+    }
+
+    fn set(&mut self, control: Control, keycodes: Vec<Keycode>) {
+        for (_, keys) in self.keys.iter_mut() {
+            keys.retain(|k| !keycodes.contains(k));
+        }
+        match self.keys.iter_mut().find(|(c, _)| *c == control) {
+            Some((_, keys)) => *keys = keycodes,
+            None => self.keys.push((control, keycodes)),
+        }
+    }
+
+    // Makes `keycode` the only key for `control`, taking it away from any other control
+    pub fn bind(&mut self, control: Control, keycode: Keycode) {
+        self.set(control, vec![keycode]);
+    }
+}
+
+// The in-app rebinding screen: steps through the CONTROLS the game has wired, asking for a key
+// for each. A key can only be taken from a control that is still to come, which must then get
+// a new key if it has none left. Keys of earlier or unwired controls are refused.
+pub struct Rebinding {
+    bindings: KeyBindings,
+    steps: Vec<usize>, // Indexes into CONTROLS
+    next: usize,
+    refused: Option<String>,
+}
+
+impl Rebinding {
+    // None when the game wires none of the CONTROLS, as there is nothing to ask for
+    pub fn new(current: &KeyBindings, wired: &[Control]) -> Option<Self> {
+        let steps: Vec<usize> = (0..CONTROLS.len()).filter(|&i| wired.contains(&CONTROLS[i].0)).collect();
+        (!steps.is_empty()).then(|| Self {
+            bindings: current.clone(),
+            steps,
+            next: 0,
+            refused: None,
+        })
+    }
+
+    pub fn prompt(&self) -> String {
+        let (control, _, label) = CONTROLS[self.steps[self.next]];
+        let current: Vec<String> = self.bindings.keys_for(control).iter().map(|k| k.name()).collect();
+        let prompt = match current.is_empty() {
+            true => format!("Press a key for {} (now none), Esc to cancel", label),
+            false => format!("Press a key for {} (now {}), Backspace to keep, Esc to cancel", label, current.join(", ")),
+        };
+        match &self.refused {
+            Some(reason) => format!("{}. {}", reason, prompt),
+            None => prompt,
+        }
+    }
+
+    // Takes the next key press. Returns the finished bindings after the last control.
+    pub fn key(&mut self, keycode: Keycode) -> Option<KeyBindings> {
+        let control = CONTROLS[self.steps[self.next]].0;
+        self.refused = None;
+        if keycode == Keycode::Backspace || keycode == REBIND_KEY {
+            if self.bindings.keys_for(control).is_empty() {
+                self.refused = Some("Nothing to keep".to_string());
+                return None
+            }
+        } else if let Some(owner) = self.bindings.control_for(keycode).filter(|&c| c != control && !self.is_still_to_come(c)) {
+            let (_, _, label) = CONTROLS.iter().find(|(c, _, _)| *c == owner).unwrap();
+            self.refused = Some(format!("{} is already used for {}", keycode.name(), label));
+            return None
+        } else {
+            self.bindings.bind(control, keycode);
+        }
+        self.next += 1;
+        (self.next == self.steps.len()).then(|| self.bindings.clone())
+    }
+
+    fn is_still_to_come(&self, control: Control) -> bool {
+        self.steps[self.next + 1..].iter().any(|&i| CONTROLS[i].0 == control)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(entries: &[(&str, &[&str])]) -> BTreeMap<String, Vec<String>> {
+        entries.iter()
+            .map(|(name, keys)| (name.to_string(), keys.iter().map(|k| k.to_string()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn config_round_trip() {
+        let defaults = KeyBindings::default();
+        assert_eq!(KeyBindings::from_config(&defaults.to_config()).unwrap(), defaults);
+
+        let keys = KeyBindings::from_config(&table(&[("p1-fire", &["Return"]), ("coin", &["c"])])).unwrap();
+        assert_eq!(keys.keys_for(Control::P1Fire), [Keycode::Return]);
+        assert_eq!(keys.keys_for(Control::Coin), [Keycode::C]);
+        assert_eq!(keys.keys_for(Control::P1Left), [Keycode::Left]);
+        assert_eq!(keys.control_for(Keycode::Space), None);
+        assert_eq!(keys.to_config()["coin"], vec!["C".to_string()]);
+        assert_eq!(KeyBindings::from_config(&keys.to_config()).unwrap(), keys);
+    }
+
+    #[test]
+    fn reserved_and_unknown_keys_rejected() {
+        for key in ["Escape", "F1"] {
+            let err = KeyBindings::from_config(&table(&[("p1-fire", &[key])])).unwrap_err();
+            assert!(err.contains("reserved"), "{}", err);
+        }
+        assert!(KeyBindings::from_config(&table(&[("p1-fire", &["Nope"])])).is_err());
+        assert!(KeyBindings::from_config(&table(&[("p3-fire", &["Space"])])).is_err());
+    }
+
+    #[test]
+    fn clashing_keys_rejected() {
+        let err = KeyBindings::from_config(&table(&[("p1-fire", &["X"]), ("p2-fire", &["X"])])).unwrap_err();
+        assert!(err.contains("both p1-fire and p2-fire"), "{}", err);
+
+        // A is player 2's only fire key
+        let err = KeyBindings::from_config(&table(&[("p1-fire", &["A"])])).unwrap_err();
+        assert!(err.contains("p2-fire"), "{}", err);
+
+        // Player 1 keeps Left Ctrl when player 2 takes Space
+        let keys = KeyBindings::from_config(&table(&[("p2-fire", &["Space"])])).unwrap();
+        assert_eq!(keys.keys_for(Control::P1Fire), [Keycode::LCtrl]);
+        assert_eq!(keys.keys_for(Control::P2Fire), [Keycode::Space]);
+    }
+
+    #[test]
+    fn rebinding_steps_through_wired_controls() {
+        let wired = [Control::P1Left, Control::P1Fire, Control::Coin, Control::P2Fire];
+        let mut screen = Rebinding::new(&KeyBindings::default(), &wired).unwrap();
+        assert!(screen.prompt().starts_with("Press a key for P1 Left (now Left),"), "{}", screen.prompt());
+        assert_eq!(screen.key(Keycode::Z), None);
+        assert_eq!(screen.key(Keycode::Backspace), None); // P1 Fire keeps its keys
+
+        assert_eq!(screen.key(Keycode::Z), None);
+        assert!(screen.prompt().starts_with("Z is already used for P1 Left. Press a key for P2 Fire"), "{}", screen.prompt());
+        assert_eq!(screen.key(Keycode::Up), None); // P1 Up isn't wired, but keeps its key
+        assert!(screen.prompt().starts_with("Up is already used for P1 Up."), "{}", screen.prompt());
+        assert_eq!(screen.key(REBIND_KEY), None); // F1 keeps as well
+
+        let keys = screen.key(Keycode::C).unwrap();
+        assert_eq!(keys.keys_for(Control::P1Left), [Keycode::Z]);
+        assert_eq!(keys.keys_for(Control::P1Fire), [Keycode::LCtrl, Keycode::Space]);
+        assert_eq!(keys.keys_for(Control::Coin), [Keycode::C]);
+        assert_eq!(keys.keys_for(Control::P2Fire), [Keycode::A]);
+        assert_eq!(keys.keys_for(Control::P1Up), [Keycode::Up]);
+    }
+
+    #[test]
+    fn rebinding_moves_keys_from_later_controls() {
+        let mut screen = Rebinding::new(&KeyBindings::default(), &[Control::P1Fire, Control::P2Fire]).unwrap();
+        assert_eq!(screen.key(Keycode::A), None);
+        assert_eq!(screen.prompt(), "Press a key for P2 Fire (now none), Esc to cancel");
+        assert_eq!(screen.key(Keycode::Backspace), None);
+        assert!(screen.prompt().starts_with("Nothing to keep. "), "{}", screen.prompt());
+
+        let keys = screen.key(Keycode::Q).unwrap();
+        assert_eq!(keys.keys_for(Control::P1Fire), [Keycode::A]);
+        assert_eq!(keys.keys_for(Control::P2Fire), [Keycode::Q]);
+    }
+
+    #[test]
+    fn nothing_to_rebind() {
+        assert!(Rebinding::new(&KeyBindings::default(), &[]).is_none());
+    }
+}
@@ -1,34 +1,223 @@
-use core_8080::CPU;
-use std::{env, process};
-use std::fs::File;
-use std::io::Read;
+mod controls;
+mod gamepad;
+mod keyboard;
+mod settings;
 
-fn main() {
-    println!("Welcome to Space Invaders!");
+use clap::Parser;
+use core_8080::{Machine, MachineConfig, FrameEvent, Game, RomDatabase, RomSource, Control, ColourOverlay, FRAMES_PER_SECOND, SCREEN_WIDTH, SCREEN_HEIGHT};
+use controls::{HeldControls, Source};
+use gamepad::Gamepads;
+use keyboard::Rebinding;
+use sdl2::render::WindowCanvas;
+use settings::{Args, Settings};
+use sdl2::event::Event;
+use sdl2::pixels::PixelFormatEnum;
+use std::{fs, process, thread};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+const TITLE: &str = "Space Invaders";
 
-    // let path = "/home/nolanjome/Rust/space-invaders-rs/roms/invaders.h";
+fn main() {
+    let settings = match Settings::from_args(Args::parse()) {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        },
+    };
 
-    let args: Vec<_> = env::args().collect();
+    let loaded = match settings.game {
+        Some(game) => load_unverified(game, &settings.roms),
+        None => identify(&settings.roms),
+    };
+    let (mut config, rom_buffer) = match loaded {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            eprintln!("Error loading ROM: {}", err);
+            process::exit(1);
+        },
+    };
+    if let Some(dips) = settings.dips {
+        config.dip_switches = dips;
+    }
+    let overlay = settings.colour_overlay(&config);
 
-    // Open ROM file
-    let mut rom_file = match File::open(args[1].clone()) {
-        Ok(file) => file,
+    let mut machine = match Machine::new(config, &rom_buffer) {
+        Ok(machine) => machine,
         Err(err) => {
-            eprintln!("Error opening file: {}", err);
+            eprintln!("Error starting machine: {}", err);
             process::exit(1);
         },
     };
 
-    let mut rom_buffer = Vec::new();
-    if let Err(e) = rom_file.read_to_end(&mut rom_buffer) {
-        eprintln!("Error reading ROM: {}", e);
+    if let Err(err) = run(&mut machine, &settings, &overlay) {
+        eprintln!("{}", err);
         process::exit(1);
     }
+}
 
-    let mut cpu = CPU::new();
-    cpu.load_rom(&rom_buffer).unwrap();
+// Looks the ROMs up in the database, which picks the machine to run them on. They can come from
+// a directory, a zip, a combined image or the individual chip files.
+fn identify(roms: &[PathBuf]) -> Result<(MachineConfig, Vec<u8>), String> {
+    let rom_source = match roms {
+        [path] => RomSource::from_path(path),
+        paths => RomSource::Files(paths.to_vec()),
+    };
+    let database = RomDatabase::builtin();
+    let (known, rom_buffer) = database.identify(&rom_source).map_err(|err| err.to_string())?;
+    println!("Found {}, {} ({})", known.title, known.revision, known.set.name);
+    Ok((known.config.clone(), rom_buffer))
+}
 
-    for _i in 0..10000 {
-        let _ = cpu.tick().unwrap();
+// Runs files the database doesn't know on the board the player named. They are joined in the
+// order given and laid out through the board's ROM banks, with nothing to check them against.
+fn load_unverified(game: Game, roms: &[PathBuf]) -> Result<(MachineConfig, Vec<u8>), String> {
+    let mut data = Vec::new();
+    for path in roms {
+        if path.is_dir() {
+            return Err(format!("{} is a directory. With --game, list the ROM files in load order.", path.display()))
+        }
+        data.extend(fs::read(path).map_err(|err| format!("could not read {}: {}", path.display(), err))?);
     }
+    let config = MachineConfig::for_game(game);
+    let rom_buffer = config.memory.image_from(&data).map_err(|err| format!("{} for {}", err, game))?;
+    println!("Running {} from unverified ROM files", game);
+    Ok((config, rom_buffer))
+}
+
+// Runs the game in a window until it is closed, one machine frame per 60 Hz tick
+fn run(machine: &mut Machine, settings: &Settings, overlay: &ColourOverlay) -> Result<(), String> {
+    // Sound and save states aren't implemented yet, so their settings only get a warning
+    if settings.audio {
+        eprintln!("Sound is not yet implemented, playing without it");
+    }
+    if let Some(dir) = &settings.save_state_dir {
+        eprintln!("Save states are not yet implemented, {} is not used", dir.display());
+    }
+    let sdl = sdl2::init()?;
+    let video = sdl.video()?;
+    let mut window = video.window(TITLE, SCREEN_WIDTH as u32 * settings.scale, SCREEN_HEIGHT as u32 * settings.scale);
+    window.position_centered();
+    if settings.fullscreen {
+        window.fullscreen_desktop();
+    }
+    let window = window.build().map_err(|err| err.to_string())?;
+    let mut canvas = window.into_canvas().build().map_err(|err| err.to_string())?;
+    let texture_creator = canvas.texture_creator();
+    let mut texture = texture_creator
+        .create_texture_streaming(PixelFormatEnum::RGBA32, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32)
+        .map_err(|err| err.to_string())?;
+    // Pads are a nice to have, so a broken controller subsystem only costs a warning
+    let mut gamepads = sdl.game_controller()
+        .map_err(|err| eprintln!("Gamepads unavailable: {}", err))
+        .ok()
+        .map(|subsystem| Gamepads::new(subsystem, settings.gamepad.clone()));
+    let mut event_pump = sdl.event_pump()?;
+    let mut keys = settings.keys.clone();
+    let mut rebinding: Option<Rebinding> = None;
+    let mut held = HeldControls::default();
+
+    let frame_time = Duration::from_secs(1).div_f64(FRAMES_PER_SECOND as f64 * settings.speed);
+    let mut next_frame = Instant::now();
+    'running: loop {
+        for event in event_pump.poll_iter() {
+            // Pads are paused while rebinding, but keep being tracked and hot-plugged
+            if gamepads.as_mut().is_some_and(|pads| pads.handle(&event, &mut held, &mut machine.inputs())) {
+                continue
+            }
+            if let Some(screen) = rebinding.as_mut() {
+                match event {
+                    Event::Quit { .. } => break 'running,
+                    Event::KeyDown { keycode: Some(keyboard::QUIT_KEY), .. } => {
+                        rebinding = None;
+                        set_title(&mut canvas, TITLE)?;
+                        if let Some(pads) = gamepads.as_mut() {
+                            pads.pads.resume(&mut held, &mut machine.inputs());
+                        }
+                    },
+                    Event::KeyDown { keycode: Some(keycode), repeat: false, .. } => match screen.key(keycode) {
+                        Some(new_keys) => {
+                            keys = new_keys;
+                            rebinding = None;
+                            set_title(&mut canvas, TITLE)?;
+                            if let Some(pads) = gamepads.as_mut() {
+                                pads.pads.resume(&mut held, &mut machine.inputs());
+                            }
+                            if let Err(err) = settings.save_key_bindings(&keys) {
+                                eprintln!("Key bindings not saved: {}", err);
+                            }
+                        },
+                        None => show_prompt(&mut canvas, &screen.prompt())?,
+                    },
+                    _ => (),
+                }
+                continue
+            }
+
+            match event {
+                Event::Quit { .. } | Event::KeyDown { keycode: Some(keyboard::QUIT_KEY), .. } => break 'running,
+                Event::KeyDown { keycode: Some(keyboard::REBIND_KEY), repeat: false, .. } => {
+                    let wired: Vec<Control> = machine.config().inputs.bits.iter().map(|bit| bit.control).collect();
+                    let Some(screen) = Rebinding::new(&keys, &wired) else {
+                        eprintln!("This game has no controls to rebind");
+                        continue
+                    };
+                    // Let go of everything so no control stays held while the game is paused
+                    held.release_all(&mut machine.inputs());
+                    if let Some(pads) = gamepads.as_mut() {
+                        pads.pads.pause(&mut held, &mut machine.inputs());
+                    }
+                    show_prompt(&mut canvas, &screen.prompt())?;
+                    rebinding = Some(screen);
+                },
+                Event::KeyDown { keycode: Some(keycode), repeat: false, .. } => match keys.control_for(keycode) {
+                    // A key press drops one coin, played out as a proper switch pulse
+                    Some(Control::Coin) => machine.inputs().insert_coin(),
+                    Some(control) => held.press(control, Source::Key(keycode), &mut machine.inputs()),
+                    None => (),
+                },
+                Event::KeyUp { keycode: Some(keycode), .. } => match keys.control_for(keycode) {
+                    Some(Control::Coin) | None => (),
+                    Some(control) => held.release(control, Source::Key(keycode), &mut machine.inputs()),
+                },
+                _ => (),
+            }
+        }
+
+        // The game is paused while the rebinding screen is up
+        if rebinding.is_none() {
+            let summary = machine.run_frame().map_err(|err| format!("Emulation stopped: {}", err))?;
+            if summary.events.iter().any(|event| matches!(event, FrameEvent::WatchdogReset { .. })) {
+                eprintln!("Watchdog timed out, resetting");
+            }
+        }
+
+        let screen = machine.framebuffer().map_err(|err| format!("Emulation stopped: {}", err))?;
+        texture.update(None, &overlay.render(&screen), SCREEN_WIDTH * 4).map_err(|err| err.to_string())?;
+        canvas.clear();
+        canvas.copy(&texture, None, None)?;
+        canvas.present();
+
+        // Sleep off the rest of the frame. If we fall behind, skip ahead rather than racing.
+        next_frame += frame_time;
+        let now = Instant::now();
+        if next_frame > now {
+            thread::sleep(next_frame - now);
+        } else {
+            next_frame = now;
+        }
+    }
+    Ok(())
+}
+
+fn set_title(canvas: &mut WindowCanvas, title: &str) -> Result<(), String> {
+    canvas.window_mut().set_title(title).map_err(|err| err.to_string())
+}
+
+// The rebinding screen talks to the player through the window title, and through the terminal
+// as well since a fullscreen window shows no title
+fn show_prompt(canvas: &mut WindowCanvas, prompt: &str) -> Result<(), String> {
+    println!("{}", prompt);
+    set_title(canvas, prompt)
 }
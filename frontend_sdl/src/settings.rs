@@ -0,0 +1,368 @@
+use clap::Parser;
+use core_8080::{ColourOverlay, DipSwitches, Game, MachineConfig};
+use crate::gamepad::PadBindings;
+use crate::keyboard::KeyBindings;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_SCALE: u32 = 3;
+
+#[derive(Parser, Debug)]
+#[command(name = "frontend_sdl", version, about = "Space Invaders and other Midway 8080 games")]
+pub struct Args {
+    /// ROM directory, zip, combined image or the individual chip files
+    pub roms: Vec<PathBuf>,
+
+    /// Run the ROM files, joined in the order given, as this game without looking them up in the
+    /// ROM database: invaders, invadpt2, lrescue, gunfight, seawolf or boothill
+    #[arg(long, value_name = "GAME")]
+    pub game: Option<String>,
+
+    /// Config file to read instead of the default one
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Window size as a multiple of the 224x256 screen
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..=10))]
+    pub scale: Option<u32>,
+
+    /// Start in fullscreen
+    #[arg(long, overrides_with = "windowed")]
+    pub fullscreen: bool,
+
+    /// Start in a window, overriding the config file
+    #[arg(long, overrides_with = "fullscreen")]
+    pub windowed: bool,
+
+    /// Operator DIP switches, e.g. ships=5,bonus=1500,coin-info=off
+    #[arg(long, value_name = "SETTINGS")]
+    pub dips: Option<String>,
+
+    /// Colour overlay: auto, none, midway, taito or bands such as 32-63:ff2020
+    #[arg(long, value_name = "OVERLAY")]
+    pub overlay: Option<String>,
+
+    /// Emulation speed, 1.0 being the arcade's 60 frames a second
+    #[arg(long, value_name = "FACTOR")]
+    pub speed: Option<f64>,
+
+    /// Play sound (not yet implemented)
+    #[arg(long, overrides_with = "no_audio")]
+    pub audio: bool,
+
+    /// Mute sound, overriding the config file (not yet implemented)
+    #[arg(long, overrides_with = "audio")]
+    pub no_audio: bool,
+
+    /// Directory for save states (not yet implemented)
+    #[arg(long, value_name = "DIR")]
+    pub save_state_dir: Option<PathBuf>,
+}
+
+// The persistent settings file. Everything is optional, and the command line wins over it.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ConfigFile {
+    pub rom: Option<PathBuf>,
+    pub game: Option<String>,
+    pub scale: Option<u32>,
+    pub fullscreen: Option<bool>,
+    pub dips: Option<String>,
+    pub overlay: Option<String>,
+    pub speed: Option<f64>,
+    pub audio: Option<bool>, // Not yet implemented
+    pub save_state_dir: Option<PathBuf>, // Not yet implemented
+    pub keys: Option<BTreeMap<String, Vec<String>>>,
+    pub gamepad: Option<GamepadConfig>,
+}
+
+// The [gamepad] table: SDL button names for each action, the stick axes and their deadzone.
+// The same mapping applies to every pad, whichever player it plays.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct GamepadConfig {
+    pub left: Option<Vec<String>>,
+    pub right: Option<Vec<String>>,
+    pub up: Option<Vec<String>>,
+    pub down: Option<Vec<String>>,
+    pub fire: Option<Vec<String>>,
+    pub start: Option<Vec<String>>,
+    pub coin: Option<Vec<String>>,
+    pub x_axis: Option<String>,
+    pub y_axis: Option<String>,
+    pub deadzone: Option<i16>,
+}
+
+impl ConfigFile {
+    // A missing file just means nothing has been configured yet
+    pub fn load(path: &Path) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(text) => toml::from_str(&text).map_err(|err| format!("Error in {}: {}", path.display(), err)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(format!("Error reading {}: {}", path.display(), err)),
+        }
+    }
+}
+
+// Replaces the [keys] table in the text of a config file, keeping the rest of it, comments
+// and all, as it was
+fn with_key_bindings(text: &str, keys: &KeyBindings) -> Result<String, String> {
+    let mut document = text.parse::<toml_edit::DocumentMut>().map_err(|err| err.to_string())?;
+    if !document.get("keys").is_some_and(toml_edit::Item::is_table) {
+        document["keys"] = toml_edit::table();
+    }
+    let table = document["keys"].as_table_mut().unwrap();
+    table.retain(|name, _| keys.to_config().contains_key(name));
+    for (name, key_names) in keys.to_config() {
+        table[&name] = toml_edit::value(key_names.into_iter().collect::<toml_edit::Array>());
+    }
+    Ok(document.to_string())
+}
+
+// $XDG_CONFIG_HOME/space-invaders-rs/config.toml, falling back to ~/.config
+pub fn default_config_path() -> Option<PathBuf> {
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_dir.join("space-invaders-rs").join("config.toml"))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OverlaySetting {
+    Auto, // Whatever the ROM database lists for the game
+    Fixed(ColourOverlay),
+}
+
+// The settings the frontend runs with, after merging the config file and the command line
+#[derive(Debug)]
+pub struct Settings {
+    pub roms: Vec<PathBuf>,
+    pub game: Option<Game>, // None to identify the ROMs from the database
+    pub scale: u32,
+    pub fullscreen: bool,
+    pub dips: Option<DipSwitches>,
+    pub overlay: OverlaySetting,
+    pub speed: f64,
+    pub audio: bool, // Off by default, as no sound is played yet
+    pub save_state_dir: Option<PathBuf>,
+    pub keys: KeyBindings,
+    pub gamepad: PadBindings,
+    pub config_path: Option<PathBuf>,
+}
+
+impl Settings {
+    pub fn from_args(args: Args) -> Result<Self, String> {
+        let config_path = args.config.clone().or_else(default_config_path);
+        let config = match &config_path {
+            Some(path) => ConfigFile::load(path)?,
+            None => ConfigFile::default(),
+        };
+        Self::merge(config, args, config_path)
+    }
+
+    fn merge(config: ConfigFile, args: Args, config_path: Option<PathBuf>) -> Result<Self, String> {
+        let roms = if args.roms.is_empty() { config.rom.into_iter().collect() } else { args.roms };
+        if roms.is_empty() {
+            return Err("No ROM given. Pass a ROM path or set `rom` in the config file (see --help).".to_string())
+        }
+
+        let game = args.game.or(config.game)
+            .map(|game| game.parse::<Game>().map_err(|err| format!("Error in game: {}", err)))
+            .transpose()?;
+
+        let scale = args.scale.or(config.scale).unwrap_or(DEFAULT_SCALE);
+        if !(1..=10).contains(&scale) {
+            return Err(format!("Scale must be between 1 and 10, not {}", scale))
+        }
+
+        let fullscreen = match (args.fullscreen, args.windowed) {
+            (true, _) => true,
+            (_, true) => false,
+            _ => config.fullscreen.unwrap_or(false),
+        };
+
+        let dips = args.dips.or(config.dips)
+            .map(|dips| dips.parse::<DipSwitches>().map_err(|err| format!("Error in DIP settings: {}", err)))
+            .transpose()?;
+
+        let overlay = match args.overlay.or(config.overlay).as_deref().map(str::trim) {
+            None | Some("auto") => OverlaySetting::Auto,
+            Some("none") => OverlaySetting::Fixed(ColourOverlay::default()),
+            Some("midway") => OverlaySetting::Fixed(ColourOverlay::midway()),
+            Some("taito") => OverlaySetting::Fixed(ColourOverlay::taito()),
+            Some(bands) => OverlaySetting::Fixed(bands.parse().map_err(|err| format!("Error in overlay: {}", err))?),
+        };
+
+        let speed = args.speed.or(config.speed).unwrap_or(1.0);
+        if !(0.1..=10.0).contains(&speed) {
+            return Err(format!("Speed must be between 0.1 and 10, not {}", speed))
+        }
+
+        let audio = match (args.audio, args.no_audio) {
+            (true, _) => true,
+            (_, true) => false,
+            _ => config.audio.unwrap_or(false),
+        };
+
+        let keys = match &config.keys {
+            Some(table) => KeyBindings::from_config(table)?,
+            None => KeyBindings::default(),
+        };
+
+        let gamepad = match &config.gamepad {
+            Some(table) => PadBindings::from_config(table)?,
+            None => PadBindings::default(),
+        };
+
+        Ok(Self {
+            roms,
+            game,
+            scale,
+            fullscreen,
+            dips,
+            overlay,
+            speed,
+            audio,
+            save_state_dir: args.save_state_dir.or(config.save_state_dir),
+            keys,
+            gamepad,
+            config_path,
+        })
+    }
+
+    // Writes new bindings into the config file, leaving everything else in it untouched
+    pub fn save_key_bindings(&self, keys: &KeyBindings) -> Result<(), String> {
+        let path = self.config_path.as_ref().ok_or("No config file location to save key bindings to")?;
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(format!("Error reading {}: {}", path.display(), err)),
+        };
+        let text = with_key_bindings(&text, keys).map_err(|err| format!("Error in {}: {}", path.display(), err))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|err| format!("Error creating {}: {}", dir.display(), err))?;
+        }
+        fs::write(path, text).map_err(|err| format!("Error writing {}: {}", path.display(), err))
+    }
+
+    pub fn colour_overlay(&self, config: &MachineConfig) -> ColourOverlay {
+        match &self.overlay {
+            OverlaySetting::Auto => ColourOverlay::preset(config.overlay),
+            OverlaySetting::Fixed(overlay) => overlay.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merge(config: &str, args: &[&str]) -> Result<Settings, String> {
+        let config: ConfigFile = toml::from_str(config).unwrap();
+        let args = Args::try_parse_from(["frontend_sdl"].iter().chain(args)).unwrap();
+        Settings::merge(config, args, None)
+    }
+
+    #[test]
+    fn command_line_wins_over_config() {
+        let config = "rom = \"invaders.zip\"\nscale = 2\nspeed = 0.5\noverlay = \"taito\"";
+        let settings = merge(config, &[]).unwrap();
+        assert_eq!(settings.roms, vec![PathBuf::from("invaders.zip")]);
+        assert_eq!(settings.scale, 2);
+        assert_eq!(settings.speed, 0.5);
+        assert_eq!(settings.overlay, OverlaySetting::Fixed(ColourOverlay::taito()));
+
+        let settings = merge(config, &["sitv", "--scale", "4", "--overlay", "auto"]).unwrap();
+        assert_eq!(settings.roms, vec![PathBuf::from("sitv")]);
+        assert_eq!(settings.scale, 4);
+        assert_eq!(settings.speed, 0.5);
+        assert_eq!(settings.overlay, OverlaySetting::Auto);
+
+        assert_eq!(merge("", &["invaders.zip"]).unwrap().scale, DEFAULT_SCALE);
+        assert!(merge("", &[]).is_err());
+    }
+
+    #[test]
+    fn explicit_game() {
+        assert_eq!(merge("", &["roms"]).unwrap().game, None);
+        assert_eq!(merge("game = \"seawolf\"", &["roms"]).unwrap().game, Some(Game::SeaWolf));
+        assert_eq!(merge("game = \"seawolf\"", &["roms", "--game", "lrescue"]).unwrap().game, Some(Game::LunarRescue));
+        let err = merge("", &["roms", "--game", "galaxian"]).unwrap_err();
+        assert!(err.contains("galaxian"), "{}", err);
+    }
+
+    #[test]
+    fn fullscreen_overrides() {
+        assert!(!merge("", &["roms"]).unwrap().fullscreen);
+        assert!(merge("fullscreen = true", &["roms"]).unwrap().fullscreen);
+        assert!(!merge("fullscreen = true", &["roms", "--windowed"]).unwrap().fullscreen);
+        assert!(merge("fullscreen = false", &["roms", "--fullscreen"]).unwrap().fullscreen);
+        // The last of the two flags wins
+        assert!(merge("", &["roms", "--windowed", "--fullscreen"]).unwrap().fullscreen);
+        assert!(!merge("", &["roms", "--fullscreen", "--windowed"]).unwrap().fullscreen);
+    }
+
+    #[test]
+    fn audio_and_save_states() {
+        let settings = merge("", &["roms"]).unwrap();
+        assert!(!settings.audio);
+        assert_eq!(settings.save_state_dir, None);
+        assert!(merge("audio = true", &["roms"]).unwrap().audio);
+        assert!(merge("audio = false", &["roms", "--audio"]).unwrap().audio);
+        assert!(!merge("audio = true", &["roms", "--no-audio"]).unwrap().audio);
+        assert!(!merge("", &["roms", "--audio", "--no-audio"]).unwrap().audio);
+
+        let config = "save-state-dir = \"states\"";
+        assert_eq!(merge(config, &["roms"]).unwrap().save_state_dir, Some(PathBuf::from("states")));
+        let settings = merge(config, &["roms", "--save-state-dir", "/tmp/si"]).unwrap();
+        assert_eq!(settings.save_state_dir, Some(PathBuf::from("/tmp/si")));
+    }
+
+    #[test]
+    fn range_checks() {
+        assert!(merge("scale = 0", &["roms"]).is_err());
+        assert!(merge("scale = 11", &["roms"]).is_err());
+        assert!(Args::try_parse_from(["frontend_sdl", "roms", "--scale", "11"]).is_err());
+        assert!(merge("speed = 0.05", &["roms"]).is_err());
+        assert!(merge("", &["roms", "--speed", "20"]).is_err());
+        assert_eq!(merge("", &["roms", "--speed", "10"]).unwrap().speed, 10.0);
+    }
+
+    #[test]
+    fn overlay_keywords_or_bands() {
+        let overlay = |text: &str| merge("", &["roms", "--overlay", text]).map(|s| s.overlay);
+        assert_eq!(overlay("none").unwrap(), OverlaySetting::Fixed(ColourOverlay::default()));
+        assert_eq!(overlay(" midway ").unwrap(), OverlaySetting::Fixed(ColourOverlay::midway()));
+        assert_eq!(overlay("32-63:ff2020").unwrap(), OverlaySetting::Fixed("32-63:ff2020".parse().unwrap()));
+        assert!(overlay("rainbow").is_err());
+        assert_eq!(merge("", &["roms"]).unwrap().overlay, OverlaySetting::Auto);
+    }
+
+    #[test]
+    fn unknown_config_keys_rejected() {
+        let err = toml::from_str::<ConfigFile>("volume = 3").unwrap_err();
+        assert!(err.to_string().contains("volume"), "{}", err);
+        assert!(toml::from_str::<ConfigFile>("[gamepad]\nturbo = [\"x\"]").is_err());
+        assert!(toml::from_str::<ConfigFile>("scale = 2\n[keys]\ncoin = [\"5\"]").is_ok());
+    }
+
+    #[test]
+    fn saving_keys_keeps_the_rest_of_the_file() {
+        let text = "# My cabinet\nscale = 4 # Big\n\n[keys]\n# Fire on the trackball button\np1-fire = [\"Space\"]\nbogus = [\"Q\"]\n";
+        let keys = KeyBindings::from_config(&BTreeMap::from([("coin".to_string(), vec!["C".to_string()])])).unwrap();
+        let saved = with_key_bindings(text, &keys).unwrap();
+        assert!(saved.starts_with("# My cabinet\nscale = 4 # Big\n"), "{}", saved);
+        assert!(saved.contains("# Fire on the trackball button\np1-fire = [\"Left Ctrl\", \"Space\"]"), "{}", saved);
+        assert!(!saved.contains("bogus"), "{}", saved);
+
+        let config: ConfigFile = toml::from_str(&saved).unwrap();
+        assert_eq!(config.scale, Some(4));
+        assert_eq!(KeyBindings::from_config(config.keys.as_ref().unwrap()).unwrap(), keys);
+
+        let fresh: ConfigFile = toml::from_str(&with_key_bindings("", &keys).unwrap()).unwrap();
+        assert_eq!(fresh.keys, Some(keys.to_config()));
+    }
+}